@@ -0,0 +1,217 @@
+use std::path::Path;
+
+use crate::logics::ipc::{absolute, Command};
+use crate::logics::VERSION;
+use eyre::{eyre, Result};
+
+const USAGE: &str = "\
+Usage: llama-desktop [OPTIONS] [FILE.ctx]...
+       llama-desktop [OPTIONS] open FILE.ctx...
+       llama-desktop [OPTIONS] ask PROMPT...
+
+Options:
+  -m, --model NAME     select the model
+  -p, --prompt TEXT    put TEXT in the input box
+  -e, --endpoint URL   use this Ollama service instead of OLLAMA_HOST
+      --install        register the desktop entry and the .ctx file type
+  -h, --help           show this help
+  -V, --version        show the version
+
+Given several files, the last one is opened. When Llama Desktop is already
+running, files, prompts and the model go to that window instead.";
+
+/// What the command line asks for
+#[derive(Debug, Default)]
+pub struct Cli {
+    /// Run in this instance, or forwarded to a running one
+    pub commands: Vec<Command>,
+    pub endpoint: Option<String>,
+    pub install: bool,
+    /// Help or version was printed, nothing else to do
+    pub done: bool,
+}
+
+impl Cli {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut cli = Self::default();
+        let mut files = Vec::new();
+        let mut ask: Option<Vec<String>> = None;
+        let mut model: Option<String> = None;
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or(eyre!("{} needs a value\n\n{}", name, USAGE))
+            };
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    cli.done = true;
+                    return Ok(cli);
+                }
+                "-V" | "--version" => {
+                    println!("llama-desktop {}", VERSION);
+                    cli.done = true;
+                    return Ok(cli);
+                }
+                "-m" | "--model" => model = Some(value(arg)?),
+                "-p" | "--prompt" => {
+                    let text = value(arg)?;
+                    cli.commands.push(Command::Input { text });
+                }
+                "-e" | "--endpoint" => cli.endpoint = Some(value(arg)?),
+                "--install" => cli.install = true,
+                "--" => files.extend(args.by_ref().cloned()),
+                _ if ask.is_some() => ask.iter_mut().for_each(|words| words.push(arg.to_owned())),
+                "open" if files.is_empty() => (),
+                "ask" if files.is_empty() => ask = Some(Vec::new()),
+                _ if arg.starts_with('-') => {
+                    return Err(eyre!("unknown option {}\n\n{}", arg, USAGE))
+                }
+                _ => files.push(arg.to_owned()),
+            }
+        }
+
+        for file in files {
+            if !Path::new(&file).is_file() {
+                return Err(eyre!("no such file: {}", file));
+            }
+            cli.commands.push(Command::Open {
+                path: absolute(&file),
+            });
+        }
+        if let Some(name) = model.as_ref() {
+            // Before the rest, so a prompt goes to the chosen model
+            cli.commands.insert(
+                0,
+                Command::Model {
+                    name: name.to_owned(),
+                },
+            );
+        }
+        match ask {
+            Some(words) if words.is_empty() => {
+                return Err(eyre!("ask needs a prompt\n\n{}", USAGE))
+            }
+            Some(words) => cli.commands.push(Command::Ask {
+                prompt: words.join(" "),
+                model,
+            }),
+            None => (),
+        }
+        if cli.commands.is_empty() {
+            cli.commands.push(Command::Focus);
+        }
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// A conversation file that exists, as `Cli::parse` checks
+    fn ctx_file(name: &str) -> String {
+        let path = env::temp_dir().join(format!("llama-desktop-cli-{}.ctx", name));
+        fs::write(&path, "").unwrap();
+        absolute(&path.to_string_lossy())
+    }
+
+    #[test]
+    fn nothing_focuses() {
+        let cli = Cli::parse(&[]).unwrap();
+        assert_eq!(cli.commands, vec![Command::Focus]);
+        assert!(!cli.done);
+    }
+
+    #[test]
+    fn model_comes_first_and_files_last() {
+        let file = ctx_file("order");
+        let cli = Cli::parse(&args(&[&file, "-p", "Translate:", "--model", "mistral"])).unwrap();
+        assert_eq!(
+            cli.commands,
+            vec![
+                Command::Model {
+                    name: "mistral".to_string()
+                },
+                Command::Input {
+                    text: "Translate:".to_string()
+                },
+                Command::Open { path: file },
+            ]
+        );
+    }
+
+    #[test]
+    fn open_verb() {
+        let file = ctx_file("open");
+        let cli = Cli::parse(&args(&["open", &file])).unwrap();
+        assert_eq!(cli.commands, vec![Command::Open { path: file }]);
+    }
+
+    #[test]
+    fn ask_verb_joins_words() {
+        let cli = Cli::parse(&args(&["ask", "Why", "is", "-m", "llama3", "it", "blue?"])).unwrap();
+        // Options are still read among the words of the prompt
+        assert_eq!(
+            cli.commands,
+            vec![
+                Command::Model {
+                    name: "llama3".to_string()
+                },
+                Command::Ask {
+                    prompt: "Why is it blue?".to_string(),
+                    model: Some("llama3".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ask_needs_a_prompt() {
+        assert!(Cli::parse(&args(&["ask"])).is_err());
+    }
+
+    #[test]
+    fn after_double_dash_everything_is_a_file() {
+        let file = ctx_file("-dash");
+        let cli = Cli::parse(&args(&["--", &file])).unwrap();
+        assert_eq!(cli.commands, vec![Command::Open { path: file }]);
+        assert!(Cli::parse(&args(&["--", "--model"])).is_err());
+    }
+
+    #[test]
+    fn verbs_after_a_file_are_files() {
+        let file = ctx_file("verb");
+        assert!(Cli::parse(&args(&[&file, "ask", "hello"])).is_err());
+    }
+
+    #[test]
+    fn missing_value() {
+        for option in ["-m", "--model", "-p", "--prompt", "-e", "--endpoint"] {
+            assert!(Cli::parse(&args(&[option])).is_err(), "{}", option);
+        }
+    }
+
+    #[test]
+    fn unknown_option_and_missing_file() {
+        assert!(Cli::parse(&args(&["--frobnicate"])).is_err());
+        assert!(Cli::parse(&args(&["/no/such/file.ctx"])).is_err());
+    }
+
+    #[test]
+    fn endpoint_and_install() {
+        let cli = Cli::parse(&args(&["--install", "-e", "http://gpu-box:11434"])).unwrap();
+        assert!(cli.install);
+        assert_eq!(cli.endpoint.as_deref(), Some("http://gpu-box:11434"));
+        assert_eq!(cli.commands, vec![Command::Focus]);
+    }
+}
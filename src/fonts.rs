@@ -1,9 +1,59 @@
-use std::sync::Arc;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use eframe::egui::{self, Context, FontDefinitions, Style, TextStyle};
+use eframe::egui::{self, Context, FontDefinitions, FontFamily, FontId, TextStyle};
 use eyre::Result;
+use serde::{Deserialize, Serialize};
 
-pub fn initialize_fonts() -> Result<FontDefinitions> {
+pub const MIN_ZOOM: f32 = 0.5;
+pub const MAX_ZOOM: f32 = 3.0;
+/// How deep font folders are searched
+const MAX_DEPTH: usize = 4;
+
+/// Known system fonts covering CJK scripts, in order of preference
+static CJK_FALLBACKS: &[&str] = &[
+    "NotoSansCJK-Regular.ttc",
+    "NotoSansCJKsc-Regular.otf",
+    "NotoSansCJKjp-Regular.otf",
+    "SourceHanSans-Regular.ttc",
+    "wqy-microhei.ttc",
+    "wqy-zenhei.ttc",
+    "DroidSansFallbackFull.ttf",
+    "DroidSansFallback.ttf",
+    "PingFang.ttc",
+    "Hiragino Sans GB.ttc",
+];
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FontSettings {
+    /// Font file paths, `None` means the bundled font
+    pub body: Option<String>,
+    pub monospace: Option<String>,
+    pub heading: Option<String>,
+    pub body_size: f32,
+    pub monospace_size: f32,
+    pub heading_size: f32,
+    pub zoom: f32,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        Self {
+            body: None,
+            monospace: None,
+            heading: None,
+            body_size: 20.0,
+            monospace_size: 20.0,
+            heading_size: 20.0,
+            zoom: 1.0,
+        }
+    }
+}
+
+pub fn initialize_fonts(settings: &FontSettings) -> Result<FontDefinitions> {
     let arial = egui::FontData::from_static(include_bytes!("assets/aclonica.ttf"));
     let sans = egui::FontData::from_static(include_bytes!("assets/bellota.ttf"));
     let mono = egui::FontData::from_static(include_bytes!("assets/noto-sans-mono.ttf"));
@@ -13,6 +63,20 @@ pub fn initialize_fonts() -> Result<FontDefinitions> {
     fonts.font_data.insert("sans".into(), sans);
     fonts.font_data.insert("monospace".into(), mono);
 
+    let body = load_font(&mut fonts, "user-body", settings.body.as_deref(), "sans");
+    let monospace = load_font(
+        &mut fonts,
+        "user-monospace",
+        settings.monospace.as_deref(),
+        "monospace",
+    );
+    let heading = load_font(
+        &mut fonts,
+        "user-heading",
+        settings.heading.as_deref(),
+        &body,
+    );
+
     fonts
         .families
         .insert(egui::FontFamily::Name("arial".into()), vec!["arial".into()]);
@@ -21,32 +85,172 @@ pub fn initialize_fonts() -> Result<FontDefinitions> {
         .families
         .get_mut(&egui::FontFamily::Proportional)
         .ok_or(eyre::eyre!["couldn't load proportional font"])?
-        .insert(0, "sans".into());
+        .insert(0, body);
 
     fonts
         .families
         .get_mut(&egui::FontFamily::Monospace)
         .ok_or(eyre::eyre!["couldn't load monospace font"])?
-        .insert(0, "monospace".into());
+        .insert(0, monospace);
+
+    // Emoji are covered by egui's own fallbacks, CJK needs a system font
+    if let Some(path) = CJK_FONT.as_ref() {
+        warn!("using CJK fallback {:?}", &path);
+        match fs::read(path) {
+            Ok(data) => {
+                fonts
+                    .font_data
+                    .insert("cjk".into(), egui::FontData::from_owned(data));
+                for family in [FontFamily::Proportional, FontFamily::Monospace] {
+                    if let Some(list) = fonts.families.get_mut(&family) {
+                        list.push("cjk".into());
+                    }
+                }
+            }
+            Err(err) => eprintln!("error reading {:?}: {:?}", &path, err),
+        }
+    }
+
+    let mut heading_family = fonts
+        .families
+        .get(&FontFamily::Proportional)
+        .cloned()
+        .unwrap_or_default();
+    if !heading_family.contains(&heading) {
+        heading_family.insert(0, heading);
+    }
+    fonts
+        .families
+        .insert(FontFamily::Name("heading".into()), heading_family);
 
     Ok(fonts)
 }
 
-pub fn set_font_size(ctx: &Context, size: f32) {
-    let mut style = ctx.style().as_ref().clone();
+/// Registers a user font, returning the name to use or the fallback one
+fn load_font(
+    fonts: &mut FontDefinitions,
+    name: &str,
+    path: Option<&str>,
+    fallback: &str,
+) -> String {
+    let Some(path) = path else {
+        return fallback.to_string();
+    };
+    match fs::read(path) {
+        Ok(data) => {
+            fonts
+                .font_data
+                .insert(name.into(), egui::FontData::from_owned(data));
+            name.to_string()
+        }
+        Err(err) => {
+            eprintln!("error reading font {}: {:?}", path, err);
+            fallback.to_string()
+        }
+    }
+}
 
-    // Update font size for specific text styles
-    for text_style in &[
-        TextStyle::Body,
-        TextStyle::Heading,
-        TextStyle::Button,
-        TextStyle::Monospace,
-    ] {
-        if let Some(font_id) = style.text_styles.get_mut(text_style) {
-            font_id.size = size;
+pub fn set_font_sizes(ctx: &Context, settings: &FontSettings) {
+    ctx.all_styles_mut(|style| {
+        for (text_style, font_id) in [
+            (TextStyle::Body, FontId::proportional(settings.body_size)),
+            (TextStyle::Button, FontId::proportional(settings.body_size)),
+            (
+                TextStyle::Monospace,
+                FontId::monospace(settings.monospace_size),
+            ),
+            (
+                TextStyle::Heading,
+                FontId::new(settings.heading_size, FontFamily::Name("heading".into())),
+            ),
+        ] {
+            style.text_styles.insert(text_style, font_id);
         }
+    });
+    ctx.set_zoom_factor(settings.zoom.clamp(MIN_ZOOM, MAX_ZOOM));
+}
+
+/// Font files installed in the usual system and user locations
+#[must_use]
+pub fn system_fonts() -> Vec<PathBuf> {
+    let mut dirs = vec![
+        PathBuf::from("/usr/share/fonts"),
+        PathBuf::from("/usr/local/share/fonts"),
+        PathBuf::from("/Library/Fonts"),
+        PathBuf::from("/System/Library/Fonts"),
+    ];
+    if let Ok(home) = std::env::var("HOME") {
+        let home = PathBuf::from(home);
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join(".fonts"));
+        dirs.push(home.join("Library/Fonts"));
     }
+    let mut res = Vec::new();
+    for dir in dirs.iter() {
+        collect_fonts(dir, 0, &mut res);
+    }
+    res.sort_by_key(|path| path.file_name().map(|e| e.to_ascii_lowercase()));
+    res
+}
 
-    // Apply the modified style
-    ctx.set_style(<Style as Into<Arc<Style>>>::into(style.clone()));
+/// Font files under `dir`, without following links to folders, which may loop
+fn collect_fonts(dir: &Path, depth: usize, res: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if depth < MAX_DEPTH {
+                collect_fonts(&path, depth + 1, res);
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| matches!(e.to_lowercase().as_str(), "ttf" | "otf" | "ttc"))
+            .unwrap_or(false)
+        {
+            res.push(path);
+        }
+    }
+}
+
+/// Found once, fonts are set up again whenever a setting changes
+#[dynamic(lazy)]
+static CJK_FONT: Option<PathBuf> = find_cjk_font();
+
+fn find_cjk_font() -> Option<PathBuf> {
+    let fonts = system_fonts();
+    CJK_FALLBACKS.iter().find_map(|name| {
+        fonts
+            .iter()
+            .find(|path| path.file_name().and_then(|e| e.to_str()) == Some(name))
+            .cloned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_search_skips_linked_folders() {
+        let root = std::env::temp_dir().join(format!("llama-fonts-{}", std::process::id()));
+        let deep = root.join("a/b/c/d/e/f");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(root.join("a/Top.TTF"), b"").unwrap();
+        fs::write(root.join("a/notes.txt"), b"").unwrap();
+        fs::write(deep.join("Deep.otf"), b"").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("a/loop")).unwrap();
+        std::os::unix::fs::symlink(root.join("a/Top.TTF"), root.join("Linked.ttf")).unwrap();
+
+        let mut found = Vec::new();
+        collect_fonts(&root, 0, &mut found);
+        fs::remove_dir_all(&root).unwrap();
+        found.sort();
+        assert_eq!(found, vec![root.join("Linked.ttf"), root.join("a/Top.TTF")]);
+    }
 }
@@ -11,3 +11,25 @@ pub fn format_input_to_output(inp: impl ToString) -> String {
 }
 
 pub const HR: &str = "\n\n-----\n";
+
+/// Subsequence match score, higher is better; `None` when it doesn't match
+#[must_use]
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = pos + candidate[pos..].iter().position(|&c| c == q)?;
+        score += match last {
+            Some(last) if last + 1 == found => 5,
+            _ => 1,
+        };
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        last = Some(found);
+        pos = found + 1;
+    }
+    Some(score - (candidate.len() / 16) as i32)
+}
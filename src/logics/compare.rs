@@ -0,0 +1,242 @@
+use std::time::{Duration, Instant};
+
+use super::conversation::{ThinkSplitter, Turn};
+use super::repaint::request_repaint;
+use super::timeouts::TIMEOUTS;
+use super::titles;
+use super::{set_model, STATE};
+use crate::ollama;
+use crate::protocol::{AdditionalParams, Request, Response};
+use eyre::{eyre, Result};
+use tokio::task::JoinSet;
+
+/// One model's answer in a comparison
+#[derive(Clone, Debug, Default)]
+pub struct Candidate {
+    pub model: String,
+    pub turn: Turn,
+    pub started: Option<Instant>,
+    pub first_token: Option<Duration>,
+    pub elapsed: Option<Duration>,
+    pub prompt_tokens: Option<u64>,
+    pub tokens: Option<u64>,
+    pub tokens_per_second: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl Candidate {
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.elapsed.is_some()
+    }
+}
+
+/// The same prompt sent to several models
+///
+/// While it runs, prompts sent from the input wait in the queue, so the
+/// models being compared don't share Ollama with the conversation.
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub prompt: String,
+    pub candidates: Vec<Candidate>,
+    pub running: bool,
+    pub cancel: bool,
+    pub preferred: Option<usize>,
+    /// Tells this run apart from earlier ones whose tasks may still finish
+    run: u64,
+}
+
+impl Comparison {
+    /// Candidate to write into, unless a newer run replaced it
+    fn candidate(&mut self, run: u64, idx: usize) -> Result<&mut Candidate> {
+        if self.run != run {
+            return Err(eyre!("comparison was replaced"));
+        }
+        self.candidates
+            .get_mut(idx)
+            .ok_or(eyre!("comparison was reset"))
+    }
+}
+
+#[dynamic]
+pub static mut COMPARISON: Comparison = Comparison::default();
+
+/// Sets up a new comparison, marking it running right away so nothing
+/// else starts before its task does; returns its run id
+pub fn begin(prompt: &str, models: &[String]) -> u64 {
+    let mut comparison = COMPARISON.write();
+    comparison.run += 1;
+    comparison.prompt = prompt.to_owned();
+    comparison.candidates = models
+        .iter()
+        .map(|model| Candidate {
+            model: model.to_owned(),
+            turn: Turn::new(prompt),
+            ..Default::default()
+        })
+        .collect();
+    comparison.running = true;
+    comparison.cancel = false;
+    comparison.preferred = None;
+    comparison.run
+}
+
+/// Sends the prompt to every model, all at once or one after another
+pub async fn run(run: u64, temperature: f32, parallel: bool) {
+    let count = COMPARISON.read().candidates.len();
+    if parallel {
+        let mut tasks = JoinSet::new();
+        for idx in 0..count {
+            tasks.spawn(run_one(run, idx, temperature));
+        }
+        while tasks.join_next().await.is_some() {}
+    } else {
+        for idx in 0..count {
+            run_one(run, idx, temperature).await;
+        }
+    }
+
+    let mut comparison = COMPARISON.write();
+    if comparison.run == run {
+        comparison.running = false;
+    }
+    drop(comparison);
+    request_repaint();
+}
+
+async fn run_one(run: u64, idx: usize, temperature: f32) {
+    let result = do_run_one(run, idx, temperature).await;
+    let mut comparison = COMPARISON.write();
+    if let Ok(candidate) = comparison.candidate(run, idx) {
+        if let Err(err) = result {
+            warn!("{:?}", err);
+            candidate.error = Some(err.to_string());
+        }
+        candidate.turn.finish_thinking();
+        candidate.elapsed = candidate.started.map(|start| start.elapsed());
+        candidate.elapsed.get_or_insert(Duration::ZERO);
+    }
+    drop(comparison);
+    request_repaint();
+}
+
+async fn do_run_one(run: u64, idx: usize, temperature: f32) -> Result<()> {
+    let (model, prompt) = {
+        let mut comparison = COMPARISON.write();
+        let prompt = comparison.prompt.to_owned();
+        let candidate = comparison.candidate(run, idx)?;
+        candidate.started = Some(Instant::now());
+        (candidate.model.to_owned(), prompt)
+    };
+    check_cancel(run)?;
+
+    // Each model starts fresh, without the conversation so far
+    let payload = {
+        let state = STATE.read();
+        Request {
+            keep_alive: state.keep_alive(&model),
+            model,
+            prompt,
+            system: if state.system.is_empty() {
+                None
+            } else {
+                Some(state.system.to_owned())
+            },
+            think: state.think,
+            stream: true,
+            // Same window as the conversation, or Ollama reloads the model
+            options: Some(AdditionalParams {
+                num_ctx: state.num_ctx,
+                temperature,
+                ..Default::default()
+            }),
+        }
+    };
+    let timeout = Duration::from_secs(TIMEOUTS[STATE.read().timeout_idx] as u64);
+
+    let mut splitter = ThinkSplitter::default();
+    ollama::stream("/api/generate", &payload, timeout, |chunk: Response| {
+        check_cancel(run)?;
+        let mut comparison = COMPARISON.write();
+        let candidate = comparison.candidate(run, idx)?;
+        if candidate.first_token.is_none() {
+            candidate.first_token = candidate.started.map(|start| start.elapsed());
+        }
+        if let Some(thinking) = chunk.thinking.as_deref() {
+            candidate.turn.push_thinking(thinking);
+        }
+        splitter.feed(&mut candidate.turn, &chunk.response);
+        if chunk.done {
+            splitter.flush(&mut candidate.turn);
+            candidate.prompt_tokens = chunk.prompt_eval_count;
+            candidate.tokens = chunk.eval_count;
+            candidate.tokens_per_second = chunk
+                .eval_count
+                .zip(chunk.eval_duration)
+                .filter(|(_, duration)| *duration > 0)
+                .map(|(count, duration)| count as f64 * 1e9 / duration as f64);
+        }
+        drop(comparison);
+        request_repaint();
+        Ok(())
+    })
+    .await
+}
+
+fn check_cancel(run: u64) -> Result<()> {
+    let comparison = COMPARISON.read();
+    if comparison.cancel || comparison.run != run {
+        Err(eyre!("Comparison cancelled."))
+    } else {
+        Ok(())
+    }
+}
+
+/// Continues the conversation with the given answer and its model
+pub fn adopt(idx: usize) -> Result<()> {
+    let candidate = {
+        let mut comparison = COMPARISON.write();
+        comparison.preferred = Some(idx);
+        comparison
+            .candidates
+            .get(idx)
+            .cloned()
+            .ok_or(eyre!("no such answer"))?
+    };
+    if candidate.error.is_some() || !candidate.is_done() {
+        return Err(eyre!("answer is not complete"));
+    }
+    if !set_model(&candidate.model) {
+        return Err(eyre!("model {} not found", candidate.model));
+    }
+    titles::start(&candidate.turn.prompt);
+    let mut state = STATE.write();
+    if state.input.trim() == candidate.turn.prompt.trim() {
+        state.input.clear();
+    }
+    state.turns.push(candidate.turn);
+    state.reload = true;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_runs_are_ignored() {
+        let models = ["mistral".to_string(), "llama3".to_string()];
+        let first = begin("Why is the sky blue?", &models);
+        let second = begin("Why is the sea blue?", &models[..1]);
+        assert_ne!(first, second);
+        let mut comparison = COMPARISON.write();
+        assert!(comparison.candidate(first, 0).is_err());
+        assert!(comparison.candidate(second, 1).is_err());
+        let candidate = comparison.candidate(second, 0).unwrap();
+        assert_eq!(candidate.turn.prompt, "Why is the sea blue?");
+        assert!(comparison.running);
+        drop(comparison);
+        assert!(check_cancel(first).is_err());
+        assert!(check_cancel(second).is_ok());
+    }
+}
@@ -0,0 +1,405 @@
+use std::time::Duration;
+
+use super::conversation::estimate_tokens;
+use super::state::State;
+use super::timeouts::TIMEOUTS;
+use super::{request_repaint, STATE};
+use crate::ollama;
+use crate::protocol::{AdditionalParams, ChatRequest, ChatResponse, Message};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+pub static CONTEXT_SIZES: [usize; 7] = [2048, 4096, 8192, 16384, 32768, 65536, 131072];
+
+/// Share of the window kept free for the answer
+const ANSWER_SHARE: usize = 4;
+
+const SUMMARY_PROMPT: &str = "Summarize the conversation below into a compact memory \
+for yourself. Keep facts, decisions, names, numbers and open questions; drop small talk. \
+Answer with the summary only.";
+
+/// What to do when the conversation no longer fits the context window
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    #[default]
+    DropOldest,
+    Summarize,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 2] = [Strategy::DropOldest, Strategy::Summarize];
+
+    #[must_use]
+    pub fn key(&self) -> &'static str {
+        match self {
+            Strategy::DropOldest => "drop-oldest",
+            Strategy::Summarize => "summarize",
+        }
+    }
+
+    #[must_use]
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.key() == key.trim())
+    }
+
+    #[must_use]
+    pub fn label(&self) -> &'static str {
+        match self {
+            Strategy::DropOldest => "Drop oldest turns",
+            Strategy::Summarize => "Summarize older turns",
+        }
+    }
+}
+
+/// Messages to send for the next prompt
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub messages: Vec<Message>,
+    /// Turns left out because they didn't fit
+    pub dropped: Vec<usize>,
+}
+
+/// Tokens available for the conversation, leaving room for the answer
+#[must_use]
+pub fn budget(state: &State) -> u32 {
+    (state.num_ctx - state.num_ctx / ANSWER_SHARE) as u32
+}
+
+/// Turns that still go to the model as they are, leaving out the
+/// summarized and excluded ones
+fn history(state: &State, upto: usize) -> Vec<usize> {
+    (0..upto.min(state.turns.len()))
+        .filter(|idx| !state.turns[*idx].summarized && !state.turns[*idx].excluded)
+        .collect()
+}
+
+fn fixed_tokens(state: &State, prompt: &str) -> u32 {
+    estimate_tokens(&state.system) + estimate_tokens(&state.memory) + estimate_tokens(prompt)
+}
+
+/// Oldest unpinned turns before `upto` that don't fit with `prompt`
+#[must_use]
+pub fn dropped(state: &State, upto: usize, prompt: &str) -> Vec<usize> {
+    let budget = budget(state);
+    let mut tokens = total(state, upto, prompt);
+    let mut dropped = Vec::new();
+    for idx in history(state, upto) {
+        if tokens <= budget {
+            break;
+        }
+        let turn = &state.turns[idx];
+        if !turn.pinned {
+            tokens -= turn.tokens();
+            dropped.push(idx);
+        }
+    }
+    dropped
+}
+
+/// Builds the messages for `prompt`, given the turns before `upto`,
+/// dropping the oldest unpinned turns that don't fit
+#[must_use]
+pub fn plan(state: &State, upto: usize, prompt: &str) -> Plan {
+    let dropped = dropped(state, upto, prompt);
+    let mut messages = Vec::new();
+    if !state.system.is_empty() {
+        messages.push(Message::new("system", &state.system));
+    }
+    if !state.memory.is_empty() {
+        messages.push(Message::new(
+            "system",
+            format!("Summary of the earlier conversation:\n\n{}", state.memory),
+        ));
+    }
+    for idx in history(state, upto) {
+        if dropped.contains(&idx) {
+            continue;
+        }
+        let turn = &state.turns[idx];
+        if !turn.prompt.is_empty() {
+            messages.push(Message::new("user", &turn.prompt));
+        }
+        if !turn.answer.is_empty() {
+            messages.push(Message::new("assistant", &turn.answer));
+        }
+    }
+    messages.push(Message::new("user", prompt));
+
+    Plan { messages, dropped }
+}
+
+/// Estimated tokens the next prompt would take with the whole
+/// conversation, and the budget
+#[must_use]
+pub fn usage(state: &State) -> (u32, u32) {
+    (total(state, state.turns.len(), &state.input), budget(state))
+}
+
+/// Whether the prompt doesn't fit with all the turns before `upto`
+#[must_use]
+pub fn overflows(state: &State, upto: usize, prompt: &str) -> bool {
+    total(state, upto, prompt) > budget(state)
+}
+
+fn total(state: &State, upto: usize, prompt: &str) -> u32 {
+    fixed_tokens(state, prompt)
+        + history(state, upto)
+            .iter()
+            .map(|idx| state.turns[*idx].tokens())
+            .sum::<u32>()
+}
+
+/// Oldest unpinned turns before `upto` to fold into the memory, so the
+/// rest takes at most half of the budget
+#[must_use]
+fn to_summarize(state: &State, upto: usize) -> Vec<usize> {
+    let target = budget(state) / 2;
+    let mut tokens = total(state, upto, "");
+    let mut chosen = Vec::new();
+    for idx in history(state, upto) {
+        if tokens <= target {
+            break;
+        }
+        let turn = &state.turns[idx];
+        if !turn.pinned {
+            tokens -= turn.tokens();
+            chosen.push(idx);
+        }
+    }
+    chosen
+}
+
+/// Folds the oldest unpinned turns before `upto` into the memory until the
+/// rest takes at most half of the budget
+pub async fn summarize(upto: usize, temperature: f32) -> Result<()> {
+    let (model, chosen, text) = {
+        let state = STATE.read();
+        let chosen = to_summarize(&state, upto);
+        if chosen.is_empty() {
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        if !state.memory.is_empty() {
+            text.push_str(&format!("Earlier summary:\n{}\n\n", state.memory));
+        }
+        for turn in chosen.iter().map(|idx| &state.turns[*idx]) {
+            text.push_str(&format!(
+                "User: {}\n\nAssistant: {}\n\n",
+                turn.prompt, turn.answer
+            ));
+        }
+        let model = state
+            .models
+            .get(state.selected_model)
+            .cloned()
+            .ok_or(eyre!("no model selected"))?;
+        (model, chosen, text)
+    };
+    warn!("summarizing {} turns", chosen.len());
+
+    let options = AdditionalParams {
+        num_ctx: STATE.read().num_ctx,
+        temperature: temperature.min(0.5),
+        ..Default::default()
+    };
+    let payload = ChatRequest {
+        keep_alive: STATE.read().keep_alive(&model),
+        model,
+        messages: vec![
+            Message::new("system", SUMMARY_PROMPT),
+            Message::new("user", text),
+        ],
+        think: Some(false),
+        stream: true,
+        options,
+    };
+    let timeout = Duration::from_secs(TIMEOUTS[STATE.read().timeout_idx] as u64);
+    let mut summary = String::new();
+    ollama::stream("/api/chat", &payload, timeout, |chunk: ChatResponse| {
+        if STATE.read().escape {
+            return Err(eyre!("Escape key pressed."));
+        }
+        if let Some(message) = chunk.message {
+            summary.push_str(&message.content);
+        }
+        Ok(())
+    })
+    .await?;
+
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return Err(eyre!("the model returned an empty summary"));
+    }
+    let mut state = STATE.write();
+    state.memory = summary.to_string();
+    for idx in chosen {
+        if let Some(turn) = state.turns.get_mut(idx) {
+            turn.summarized = true;
+        }
+    }
+    Ok(())
+}
+
+/// Summarizes on request, keeping anything else from starting meanwhile
+pub async fn summarize_now(temperature: f32) {
+    let upto = {
+        let mut state = STATE.write();
+        if state.retrieving {
+            return;
+        }
+        state.retrieving = true;
+        state.turns.len()
+    };
+    if let Err(err) = summarize(upto, temperature).await {
+        eprintln!("error summarizing conversation");
+        eprintln!("{:?}", err);
+    }
+    let mut state = STATE.write();
+    state.retrieving = false;
+    state.escape = false;
+    state.reload = true;
+    drop(state);
+    request_repaint();
+}
+
+/// Drops the memory, so the summarized turns are sent as they are again
+pub fn forget() {
+    let mut state = STATE.write();
+    state.memory.clear();
+    for turn in state.turns.iter_mut() {
+        turn.summarized = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logics::conversation::Turn;
+
+    /// A turn of exactly `tokens` estimated tokens, half in the prompt
+    fn turn(tokens: u32) -> Turn {
+        // Four characters per token plus four for each message
+        let text = "x".repeat(((tokens / 2 - 4) * 4) as usize);
+        let turn = Turn {
+            prompt: text.clone(),
+            answer: text,
+            ..Default::default()
+        };
+        assert_eq!(turn.tokens(), tokens);
+        turn
+    }
+
+    /// Window of 1000 tokens leaves a budget of 750
+    fn state(turns: Vec<Turn>) -> State {
+        State {
+            num_ctx: 1000,
+            turns,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn estimate() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 5);
+        assert_eq!(estimate_tokens("abcde"), 6);
+        // Characters, not bytes
+        assert_eq!(estimate_tokens("éééé"), 5);
+    }
+
+    #[test]
+    fn everything_fits() {
+        let state = state(vec![turn(100), turn(100)]);
+        assert_eq!(budget(&state), 750);
+        assert!(dropped(&state, 2, "").is_empty());
+        let plan = plan(&state, 2, "next");
+        assert_eq!(plan.messages.len(), 5);
+        assert!(plan.dropped.is_empty());
+    }
+
+    #[test]
+    fn errors_are_not_sent() {
+        let mut state = state(vec![Turn {
+            answer: "Partial".to_string(),
+            ..Turn::new("Why?")
+        }]);
+        state.push_error("Escape key pressed.");
+        state.push_error("timed out");
+        let plan = plan(&state, 1, "next");
+        let sent: Vec<&str> = plan.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(sent, vec!["Why?", "Partial", "next"]);
+        assert_eq!(state.turns[0].error.as_deref(), Some("timed out"));
+    }
+
+    #[test]
+    fn oldest_turns_go_first() {
+        let state = state(vec![turn(300), turn(300), turn(300), turn(100)]);
+        // 1000 tokens, 250 over the budget: the first turn is enough
+        assert_eq!(dropped(&state, 4, ""), vec![0]);
+        let state = self::state(vec![turn(200), turn(200), turn(300), turn(300)]);
+        assert_eq!(dropped(&state, 4, ""), vec![0, 1]);
+    }
+
+    #[test]
+    fn pinned_turns_stay() {
+        let mut turns = vec![turn(300), turn(300), turn(300), turn(100)];
+        turns[0].pinned = true;
+        let state = state(turns);
+        assert_eq!(dropped(&state, 4, ""), vec![1]);
+        let plan = plan(&state, 4, "next");
+        assert_eq!(plan.dropped, vec![1]);
+        assert_eq!(plan.messages[0].content, state.turns[0].prompt);
+    }
+
+    #[test]
+    fn nothing_left_to_drop() {
+        let mut turns = vec![turn(500), turn(500)];
+        turns.iter_mut().for_each(|turn| turn.pinned = true);
+        let state = state(turns);
+        assert!(overflows(&state, 2, ""));
+        assert!(dropped(&state, 2, "").is_empty());
+    }
+
+    #[test]
+    fn summarized_and_excluded_turns_dont_count() {
+        let mut turns = vec![turn(400), turn(400), turn(300)];
+        turns[0].summarized = true;
+        turns[1].excluded = true;
+        let state = state(turns);
+        assert_eq!(usage(&state), (300, 750));
+        assert!(dropped(&state, 3, "").is_empty());
+        let plan = plan(&state, 3, "next");
+        assert_eq!(plan.messages.len(), 3);
+    }
+
+    #[test]
+    fn only_turns_before_upto() {
+        let state = state(vec![turn(300), turn(300), turn(300), turn(300)]);
+        assert!(dropped(&state, 2, "").is_empty());
+        assert_eq!(plan(&state, 2, "next").messages.len(), 5);
+    }
+
+    #[test]
+    fn summarize_down_to_half_the_budget() {
+        let mut turns = vec![turn(200), turn(200), turn(200), turn(200), turn(100)];
+        turns[1].pinned = true;
+        let state = state(turns);
+        // 900 tokens, down to 375 at most: skips the pinned turn
+        assert_eq!(to_summarize(&state, 5), vec![0, 2, 3]);
+        assert!(to_summarize(&self::state(vec![turn(300)]), 1).is_empty());
+    }
+
+    #[test]
+    fn system_prompt_and_memory_count() {
+        let mut state = state(vec![turn(300), turn(300)]);
+        assert!(!overflows(&state, 2, ""));
+        state.system = "x".repeat(800);
+        assert!(overflows(&state, 2, ""));
+        assert_eq!(dropped(&state, 2, ""), vec![0]);
+        state.system.clear();
+        state.memory = "x".repeat(800);
+        assert_eq!(dropped(&state, 2, ""), vec![0]);
+    }
+}
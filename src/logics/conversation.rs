@@ -0,0 +1,675 @@
+use std::time::{Duration, Instant};
+
+use crate::helpers::{format_input_to_output, HR};
+use serde::{Deserialize, Serialize};
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+const FLAGS_OPEN: &str = "<!-- flags:";
+const FLAGS_CLOSE: &str = "-->";
+
+/// One prompt and the model's answer to it
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Turn {
+    pub prompt: String,
+    pub answer: String,
+    /// Reasoning streamed before the answer, kept apart from it
+    pub thinking: String,
+    #[serde(skip)]
+    pub thinking_started: Option<Instant>,
+    pub thinking_time: Option<Duration>,
+    /// Never dropped nor summarized when the context window fills up
+    pub pinned: bool,
+    /// Kept in the conversation but never sent to the model
+    pub excluded: bool,
+    /// Folded into the conversation memory, no longer sent as is
+    pub summarized: bool,
+    /// Answer length reported by the model
+    pub answer_tokens: Option<u32>,
+    /// Why the answer stopped, shown below it but neither sent nor saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// How thinking content is written out when rendering a conversation
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Thinking {
+    Omit,
+    /// Wrapped in `<think>` tags, as saved in `.ctx` files
+    Tagged,
+    /// As a blockquote, for formats that drop raw HTML
+    Quoted,
+}
+
+/// Splits a streamed response into thinking and answer text
+///
+/// Tags may be cut across chunks, so a possible partial tag is held back
+/// until the next chunk arrives.
+#[derive(Debug, Default)]
+pub struct ThinkSplitter {
+    thinking: bool,
+    /// A thinking block was closed, even an empty one
+    closed: bool,
+    pending: String,
+}
+
+/// Piece of an answer, either Markdown text or a fenced code block
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    Code {
+        lang: &'a str,
+        code: &'a str,
+        raw: &'a str,
+    },
+}
+
+impl Turn {
+    #[must_use]
+    pub fn new(prompt: impl ToString) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push_thinking(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.thinking_started.get_or_insert_with(Instant::now);
+        self.thinking.push_str(text);
+    }
+
+    pub fn push_answer(&mut self, text: &str) {
+        // Models put blank lines after their thinking; other answers may
+        // well start with an indented code block
+        let text = if self.answer.is_empty() && !self.thinking.is_empty() {
+            text.trim_start()
+        } else {
+            text
+        };
+        if text.is_empty() {
+            return;
+        }
+        self.finish_thinking();
+        self.answer.push_str(text);
+    }
+
+    /// Stops the thinking timer, if it is running
+    pub fn finish_thinking(&mut self) {
+        if self.thinking_time.is_none() {
+            self.thinking_time = self.thinking_started.map(|start| start.elapsed());
+        }
+    }
+
+    /// Time spent thinking so far, `None` if the model did not think
+    #[must_use]
+    pub fn thinking_elapsed(&self) -> Option<Duration> {
+        self.thinking_time
+            .or_else(|| self.thinking_started.map(|start| start.elapsed()))
+    }
+
+    /// Quoted prompt as shown in the conversation
+    #[must_use]
+    pub fn quoted_prompt(&self) -> String {
+        if self.prompt.is_empty() {
+            String::new()
+        } else {
+            format_input_to_output(&self.prompt)
+        }
+    }
+
+    /// Tokens this turn takes in the context window, measured or estimated
+    #[must_use]
+    pub fn tokens(&self) -> u32 {
+        estimate_tokens(&self.prompt)
+            + self
+                .answer_tokens
+                .unwrap_or_else(|| estimate_tokens(&self.answer))
+    }
+
+    #[must_use]
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = Vec::new();
+        if self.pinned {
+            flags.push("pinned");
+        }
+        if self.excluded {
+            flags.push("excluded");
+        }
+        if self.summarized {
+            flags.push("summarized");
+        }
+        flags
+    }
+
+    fn set_flag(&mut self, flag: &str) {
+        match flag {
+            "pinned" => self.pinned = true,
+            "excluded" => self.excluded = true,
+            "summarized" => self.summarized = true,
+            _ => {
+                warn!("unknown turn flag {}", flag);
+            }
+        }
+    }
+
+    /// Pins the turn, or unpins it; a pinned turn can't be excluded
+    pub fn toggle_pinned(&mut self) {
+        self.pinned = !self.pinned;
+        if self.pinned {
+            self.excluded = false;
+        }
+    }
+
+    /// Excludes the turn from what is sent, or brings it back
+    pub fn toggle_excluded(&mut self) {
+        self.excluded = !self.excluded;
+        if self.excluded {
+            self.pinned = false;
+        }
+    }
+
+    #[must_use]
+    pub fn to_markdown(&self, thinking: Thinking) -> String {
+        let mut res = String::new();
+        let flags = self.flags();
+        if thinking == Thinking::Tagged && !flags.is_empty() {
+            res.push_str(&format!(
+                "{} {} {}\n",
+                FLAGS_OPEN,
+                flags.join(" "),
+                FLAGS_CLOSE
+            ));
+        }
+        // Formats without comments get the markers as text
+        if thinking != Thinking::Tagged && (self.pinned || self.excluded) {
+            let marker = if self.pinned {
+                "*Pinned*"
+            } else {
+                "*Excluded from the model's context*"
+            };
+            res.push_str(marker);
+            res.push_str("\n\n");
+        }
+        res.push_str(&self.quoted_prompt());
+        if !self.prompt.is_empty() {
+            res.push_str("\n\n");
+        }
+        let content = self.thinking.trim();
+        if !content.is_empty() {
+            match thinking {
+                Thinking::Omit => (),
+                Thinking::Tagged => {
+                    res.push_str(&format!("{}\n{}\n{}\n\n", THINK_OPEN, content, THINK_CLOSE));
+                }
+                Thinking::Quoted => {
+                    res.push_str(&format_input_to_output(format!(
+                        "*Thinking:*\n\n{}",
+                        content
+                    )));
+                    res.push_str("\n\n");
+                }
+            }
+        }
+        res.push_str(&self.answer);
+        res.push_str(HR);
+        res
+    }
+}
+
+#[must_use]
+pub fn render(turns: &[Turn], thinking: Thinking) -> String {
+    turns
+        .iter()
+        .map(|turn| turn.to_markdown(thinking))
+        .collect()
+}
+
+/// Splits a saved conversation back into turns
+#[must_use]
+pub fn parse(content: &str) -> Vec<Turn> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let mut turns = Vec::new();
+    let mut current: Option<Turn> = None;
+    let mut in_prompt = false;
+
+    for (idx, line) in lines.iter().enumerate() {
+        let turn = current.get_or_insert_with(|| {
+            in_prompt = true;
+            Turn::default()
+        });
+
+        if in_prompt {
+            let flags = line
+                .trim()
+                .strip_prefix(FLAGS_OPEN)
+                .and_then(|rest| rest.strip_suffix(FLAGS_CLOSE));
+            if let Some(flags) = flags.filter(|_| turn.prompt.is_empty()) {
+                flags
+                    .split_whitespace()
+                    .for_each(|flag| turn.set_flag(flag));
+                continue;
+            }
+            if let Some(quoted) = line.strip_prefix("> ").or(line.strip_prefix('>')) {
+                if !turn.prompt.is_empty() {
+                    turn.prompt.push('\n');
+                }
+                turn.prompt.push_str(quoted);
+                continue;
+            }
+            // Blank lines may come before the prompt too, in edited files
+            if line.trim().is_empty() {
+                continue;
+            }
+            in_prompt = false;
+        }
+
+        // A rule closes the turn only when a new prompt or the end follows
+        if line.trim() == "-----" {
+            let next = lines[idx + 1..].iter().find(|e| !e.trim().is_empty());
+            let opens_turn = |e: &&str| e.starts_with('>') || e.trim().starts_with(FLAGS_OPEN);
+            if next.map(opens_turn).unwrap_or(true) {
+                let mut turn = current.take().unwrap_or_default();
+                split_thinking(&mut turn);
+                turns.push(turn);
+                continue;
+            }
+        }
+        turn.answer.push_str(line);
+        turn.answer.push('\n');
+    }
+
+    if let Some(mut turn) = current {
+        split_thinking(&mut turn);
+        if !turn.prompt.is_empty() || !turn.answer.is_empty() {
+            turns.push(turn);
+        }
+    }
+    turns
+}
+
+/// Rough token count, about four characters per token plus message overhead
+#[must_use]
+pub fn estimate_tokens(text: &str) -> u32 {
+    if text.is_empty() {
+        0
+    } else {
+        (text.chars().count() as u32).div_ceil(4) + 4
+    }
+}
+
+/// Moves a leading `<think>` block of a loaded answer to its own field
+fn split_thinking(turn: &mut Turn) {
+    let answer = turn.answer.trim();
+    if let Some((thinking, answer)) = answer
+        .strip_prefix(THINK_OPEN)
+        .and_then(|rest| rest.split_once(THINK_CLOSE))
+    {
+        turn.thinking = thinking.trim().to_string();
+        turn.answer = answer.trim().to_string();
+    } else {
+        turn.answer = answer.trim_end().to_string();
+    }
+}
+
+impl ThinkSplitter {
+    /// Feeds a chunk into the turn, routing thinking and answer text
+    pub fn feed(&mut self, turn: &mut Turn, chunk: &str) {
+        self.pending.push_str(chunk);
+        loop {
+            let tag = if self.thinking {
+                THINK_CLOSE
+            } else {
+                THINK_OPEN
+            };
+            if let Some(pos) = self.pending.find(tag) {
+                let text = self.pending[..pos].to_string();
+                self.push(turn, &text);
+                self.pending.drain(..pos + tag.len());
+                self.closed |= self.thinking;
+                self.thinking = !self.thinking;
+                continue;
+            }
+            // Hold back a suffix that may be the start of a tag
+            let keep = (1..tag.len())
+                .rev()
+                .find(|len| self.pending.ends_with(&tag[..*len]))
+                .unwrap_or(0);
+            let text = self.pending[..self.pending.len() - keep].to_string();
+            self.push(turn, &text);
+            self.pending.drain(..self.pending.len() - keep);
+            break;
+        }
+    }
+
+    /// Writes out whatever was held back at the end of the stream
+    pub fn flush(&mut self, turn: &mut Turn) {
+        let text = std::mem::take(&mut self.pending);
+        self.push(turn, &text);
+        turn.finish_thinking();
+    }
+
+    fn push(&self, turn: &mut Turn, text: &str) {
+        if self.thinking {
+            turn.push_thinking(text);
+        } else if self.closed && turn.answer.is_empty() {
+            turn.push_answer(text.trim_start());
+        } else {
+            turn.push_answer(text);
+        }
+    }
+}
+
+/// Splits Markdown into text and fenced code block segments
+#[must_use]
+pub fn segments(markdown: &str) -> Vec<Segment<'_>> {
+    let mut res = Vec::new();
+    let mut text_start = 0;
+    let mut pos = 0;
+    let mut fence: Option<(char, usize, usize, &str, usize)> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        let start = pos;
+        pos += line.len();
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+
+        match fence {
+            None => {
+                let Some(marker) = marker else {
+                    continue;
+                };
+                let count = trimmed.chars().take_while(|c| *c == marker).count();
+                if count < 3 {
+                    continue;
+                }
+                let lang = trimmed[count..].split_whitespace().next().unwrap_or("");
+                if start > text_start {
+                    res.push(Segment::Text(&markdown[text_start..start]));
+                }
+                fence = Some((marker, count, start, lang, pos));
+            }
+            Some((fence_marker, count, block_start, lang, code_start)) => {
+                let closing = marker == Some(fence_marker)
+                    && trimmed.chars().take_while(|c| *c == fence_marker).count() >= count
+                    && trimmed.trim_start_matches(fence_marker).trim().is_empty();
+                if closing {
+                    res.push(Segment::Code {
+                        lang,
+                        code: &markdown[code_start..start],
+                        raw: &markdown[block_start..pos],
+                    });
+                    fence = None;
+                    text_start = pos;
+                }
+            }
+        }
+    }
+
+    match fence {
+        // Unclosed fence, usually an answer still streaming
+        Some((_, _, block_start, lang, code_start)) => res.push(Segment::Code {
+            lang,
+            code: &markdown[code_start.min(markdown.len())..],
+            raw: &markdown[block_start..],
+        }),
+        None if text_start < markdown.len() => res.push(Segment::Text(&markdown[text_start..])),
+        None => (),
+    }
+    res
+}
+
+/// File extension for a fenced code block language tag, made of letters,
+/// digits, `+`, `_` and `-` only, since it ends up in a file name
+#[must_use]
+pub fn extension_for(lang: &str) -> String {
+    let extension = match lang.to_lowercase().as_str() {
+        "rust" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" | "jsx" => "js",
+        "typescript" | "ts" | "tsx" => "ts",
+        "shell" | "bash" | "sh" | "zsh" | "console" => "sh",
+        "c++" | "cpp" => "cpp",
+        "c#" | "csharp" => "cs",
+        "golang" | "go" => "go",
+        "ruby" | "rb" => "rb",
+        "kotlin" | "kt" => "kt",
+        "markdown" | "md" => "md",
+        "yaml" | "yml" => "yml",
+        "haskell" | "hs" => "hs",
+        "perl" | "pl" => "pl",
+        "lua" => "lua",
+        "diff" | "patch" => "diff",
+        "dockerfile" => "Dockerfile",
+        "text" | "plaintext" | "" => "txt",
+        _ => {
+            let clean = lang
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '_' | '-'))
+                .collect::<String>();
+            // Tags such as `{.python}` may name a known language once cleaned
+            return if clean == lang {
+                clean
+            } else {
+                extension_for(&clean)
+            };
+        }
+    };
+    extension.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(chunks: &[&str]) -> Turn {
+        let mut turn = Turn::default();
+        let mut splitter = ThinkSplitter::default();
+        for chunk in chunks {
+            splitter.feed(&mut turn, chunk);
+        }
+        splitter.flush(&mut turn);
+        turn
+    }
+
+    #[test]
+    fn answer_keeps_its_indentation() {
+        let turn = stream(&["    let x = 1;\n", "    let y = 2;\n"]);
+        assert_eq!(turn.answer, "    let x = 1;\n    let y = 2;\n");
+        assert!(turn.thinking.is_empty());
+    }
+
+    #[test]
+    fn blank_lines_after_thinking_are_dropped() {
+        let turn = stream(&[
+            "<thi",
+            "nk>Hmm, blue.</th",
+            "ink>\n\n",
+            "  The sky is blue.",
+        ]);
+        assert_eq!(turn.thinking, "Hmm, blue.");
+        assert_eq!(turn.answer, "The sky is blue.");
+    }
+
+    #[test]
+    fn blank_lines_after_an_empty_thinking_block_are_dropped() {
+        let turn = stream(&["<think></think>", "\n\nBlue."]);
+        assert!(turn.thinking.is_empty());
+        assert_eq!(turn.answer, "Blue.");
+    }
+
+    #[test]
+    fn blank_lines_after_native_thinking_are_dropped() {
+        let mut turn = Turn::default();
+        turn.push_thinking("Hmm.");
+        turn.push_answer("\n\n");
+        turn.push_answer("Blue.\n\n    indented");
+        assert_eq!(turn.answer, "Blue.\n\n    indented");
+    }
+
+    #[test]
+    fn flags_round_trip() {
+        let mut pinned = Turn::new("Keep this");
+        pinned.answer = "Kept.\n".to_string();
+        pinned.pinned = true;
+        let mut hidden = Turn::new("Forget this");
+        hidden.answer = "Forgotten.\n".to_string();
+        hidden.excluded = true;
+        hidden.summarized = true;
+        let plain = Turn {
+            answer: "Plain.\n".to_string(),
+            ..Turn::new("Nothing special")
+        };
+
+        let content = render(&[pinned, hidden, plain], Thinking::Tagged);
+        assert!(content.starts_with("<!-- flags: pinned -->\n"));
+        let turns = parse(&content);
+        assert_eq!(turns.len(), 3);
+        assert!(turns[0].pinned && !turns[0].excluded && !turns[0].summarized);
+        assert!(!turns[1].pinned && turns[1].excluded && turns[1].summarized);
+        assert!(!turns[2].pinned && !turns[2].excluded && !turns[2].summarized);
+        assert_eq!(turns[0].prompt, "Keep this");
+        assert_eq!(turns[1].answer.trim(), "Forgotten.");
+    }
+
+    #[test]
+    fn errors_are_not_saved() {
+        let turn = Turn {
+            answer: "Partial".to_string(),
+            error: Some("Escape key pressed.".to_string()),
+            ..Turn::new("Why?")
+        };
+        let content = turn.to_markdown(Thinking::Tagged);
+        assert!(!content.contains("Escape"));
+        assert_eq!(parse(&content)[0].answer, "Partial");
+    }
+
+    #[test]
+    fn flags_only_as_comments() {
+        let mut turn = Turn::new("Keep this");
+        turn.pinned = true;
+        let content = turn.to_markdown(Thinking::Omit);
+        assert!(!content.contains(FLAGS_OPEN));
+        assert!(content.starts_with("*Pinned*"));
+    }
+
+    #[test]
+    fn flags_comment_inside_an_answer_is_text() {
+        let content = "> Question\n\n<!-- flags: pinned -->\nAnswer\n-----\n";
+        let turns = parse(content);
+        assert_eq!(turns.len(), 1);
+        assert!(!turns[0].pinned);
+        assert!(turns[0].answer.contains("<!-- flags: pinned -->"));
+    }
+
+    #[test]
+    fn unknown_flags_are_ignored() {
+        let turns = parse("<!-- flags: pinned shiny -->\n> Question\n\nAnswer\n-----\n");
+        assert!(turns[0].pinned);
+        assert_eq!(turns[0].prompt, "Question");
+    }
+
+    #[test]
+    fn parse_turns_with_thinking_and_multiline_prompts() {
+        let content = "> First line\n> second line\n\n<think>\nHmm.\n</think>\n\nBlue.\n-----\n\n> Next\n\nGreen.\n-----\n";
+        let turns = parse(content);
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].prompt, "First line\nsecond line");
+        assert_eq!(turns[0].thinking, "Hmm.");
+        assert_eq!(turns[0].answer, "Blue.");
+        assert_eq!(turns[1].prompt, "Next");
+        assert_eq!(turns[1].answer, "Green.");
+    }
+
+    #[test]
+    fn rule_followed_by_text_stays_in_the_answer() {
+        let content = "> Question\n\nBefore\n-----\n\nAfter the rule\n-----\n";
+        let turns = parse(content);
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].answer, "Before\n-----\n\nAfter the rule");
+    }
+
+    #[test]
+    fn parse_without_a_closing_rule() {
+        let turns = parse("> Question\n\nPartial answer\n");
+        assert_eq!(turns.len(), 1);
+        assert_eq!(turns[0].answer, "Partial answer");
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn segments_split_text_and_code() {
+        let markdown = "Run:\n\n```rust\nfn main() {}\n```\nDone.\n";
+        assert_eq!(
+            segments(markdown),
+            vec![
+                Segment::Text("Run:\n\n"),
+                Segment::Code {
+                    lang: "rust",
+                    code: "fn main() {}\n",
+                    raw: "```rust\nfn main() {}\n```\n",
+                },
+                Segment::Text("Done.\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_close_only_on_a_matching_fence() {
+        let markdown = "~~~~md\n```\nnot closed\n~~~\n~~~~~\n";
+        assert_eq!(
+            segments(markdown),
+            vec![Segment::Code {
+                lang: "md",
+                code: "```\nnot closed\n~~~\n",
+                raw: markdown,
+            }]
+        );
+    }
+
+    #[test]
+    fn segments_with_an_unclosed_fence() {
+        let markdown = "Streaming:\n```py\nprint(1)";
+        assert_eq!(
+            segments(markdown),
+            vec![
+                Segment::Text("Streaming:\n"),
+                Segment::Code {
+                    lang: "py",
+                    code: "print(1)",
+                    raw: "```py\nprint(1)",
+                },
+            ]
+        );
+        assert_eq!(
+            segments("```"),
+            vec![Segment::Code {
+                lang: "",
+                code: "",
+                raw: "```",
+            }]
+        );
+        assert_eq!(
+            segments("Only ``two`` ticks"),
+            vec![Segment::Text("Only ``two`` ticks")]
+        );
+    }
+
+    #[test]
+    fn extensions_for_language_tags() {
+        assert_eq!(extension_for("Rust"), "rs");
+        assert_eq!(extension_for("tsx"), "ts");
+        assert_eq!(extension_for("bash"), "sh");
+        assert_eq!(extension_for(""), "txt");
+        assert_eq!(extension_for("Dockerfile"), "Dockerfile");
+        assert_eq!(extension_for("toml"), "toml");
+        assert_eq!(extension_for("c/c++"), "cc++");
+        assert_eq!(extension_for("../x"), "x");
+        assert_eq!(extension_for("{.python}"), "py");
+        assert_eq!(extension_for("./"), "txt");
+        assert_eq!(extension_for("C#"), "cs");
+    }
+}
@@ -0,0 +1,59 @@
+use std::{fs, path::PathBuf, process};
+
+use super::paths::APP_ID;
+use eyre::{eyre, Result};
+
+const DESKTOP_ENTRY: &str = include_str!("../assets/llama-desktop.desktop");
+const MIME_TYPE: &str = include_str!("../assets/llama-desktop.xml");
+const ICON: &[u8] = include_bytes!("../assets/logo.png");
+
+fn data_home() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = std::env::var_os("HOME").ok_or(eyre!("HOME is not set"))?;
+    Ok(PathBuf::from(home).join(".local/share"))
+}
+
+fn write(path: PathBuf, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+    println!("installed {}", path.display());
+    Ok(())
+}
+
+/// Refreshes a desktop cache, which is fine to miss
+fn refresh(tool: &str, dir: PathBuf) {
+    match process::Command::new(tool).arg(&dir).status() {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("{} {} exited with {}", tool, dir.display(), status),
+        Err(err) => eprintln!("could not run {}: {}", tool, err),
+    }
+}
+
+/// Registers the desktop entry, the icon and the `.ctx` file type for the user
+pub fn install() -> Result<()> {
+    let data = data_home()?;
+    // Point the entry to this very binary, wherever it was installed
+    let exe = std::env::current_exe()?;
+    let entry = DESKTOP_ENTRY.replace("Exec=llama-desktop", &format!("Exec=\"{}\"", exe.display()));
+    write(
+        data.join("applications")
+            .join(format!("{}.desktop", APP_ID)),
+        entry.as_bytes(),
+    )?;
+    write(
+        data.join("mime/packages").join(format!("{}.xml", APP_ID)),
+        MIME_TYPE.as_bytes(),
+    )?;
+    write(
+        data.join("icons/hicolor/256x256/apps")
+            .join(format!("{}.png", APP_ID)),
+        ICON,
+    )?;
+    refresh("update-desktop-database", data.join("applications"));
+    refresh("update-mime-database", data.join("mime"));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::{fs, path::PathBuf};
+
+use super::paths::data_dir;
+use super::STATE;
+use eyre::Result;
+
+const MAX_HISTORY: usize = 1000;
+
+fn history_file() -> Option<PathBuf> {
+    Some(data_dir("history")?.join("prompts.json"))
+}
+
+/// Prompts sent in any conversation, oldest first
+#[must_use]
+pub fn load() -> Vec<String> {
+    let Some(path) = history_file() else {
+        return Vec::new();
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+            eprintln!("error reading {:?}: {:?}", &path, err);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Adds a sent prompt to the global history and writes it down
+pub fn record(prompt: &str) {
+    let history = {
+        let mut state = STATE.write();
+        state.history.retain(|e| e != prompt);
+        state.history.push(prompt.to_owned());
+        let excess = state.history.len().saturating_sub(MAX_HISTORY);
+        state.history.drain(..excess);
+        state.history.clone()
+    };
+    if let Err(err) = save(&history) {
+        eprintln!("error saving prompt history: {:?}", err);
+    }
+}
+
+fn save(history: &[String]) -> Result<()> {
+    if let Some(path) = history_file() {
+        fs::write(path, serde_json::to_string(history)?)?;
+    }
+    Ok(())
+}
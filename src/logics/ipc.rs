@@ -0,0 +1,399 @@
+use std::{
+    env,
+    fs::{self, File, TryLockError},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::paths::APP_ID;
+use super::{request_repaint, STATE};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
+use tokio::net::{UnixListener, UnixStream as AsyncUnixStream};
+
+/// Requests accepted on the socket, one JSON object per line
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    /// Raises the window
+    Focus,
+    /// Loads a conversation file, replacing the current one
+    Open { path: String },
+    /// Sends a prompt, after whatever is already queued
+    Ask {
+        prompt: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+    },
+    /// Selects a model
+    Model { name: String },
+    /// Replaces the draft in the input box
+    Input { text: String },
+    /// Reports what the app is doing
+    Status,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Reply {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Status {
+    pub model: String,
+    pub title: String,
+    pub path: Option<String>,
+    pub turns: usize,
+    pub retrieving: bool,
+    pub queued: usize,
+}
+
+/// Commands received, waiting for the UI to run them
+#[dynamic]
+pub static mut INBOX: Vec<Command> = Vec::new();
+
+/// Lock held by the instance owning the socket, until it exits
+#[dynamic]
+static mut OWNER: Option<File> = None;
+
+/// How long a second launch waits for an instance that is still starting
+const STARTUP_WAIT: Duration = Duration::from_secs(5);
+/// How long a running instance may take to answer a command
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[must_use]
+pub fn socket_path() -> PathBuf {
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        // The shared temporary folder gets a folder of our own
+        None => env::temp_dir().join(format!("{}-{}", APP_ID, uid())),
+    };
+    dir.join(format!("{}.sock", APP_ID))
+}
+
+fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail
+    unsafe { libc::getuid() }
+}
+
+/// Creates the socket's folder if needed, and checks nobody else can enter
+/// it, as they could create it first in a shared place
+fn ensure_private(dir: &Path) -> Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => (),
+        Err(err) => return Err(err.into()),
+    }
+    let meta = fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != uid() || meta.mode() & 0o077 != 0 {
+        return Err(eyre!("{:?} must be a folder only you can access", dir));
+    }
+    Ok(())
+}
+
+/// The other instance may run from another directory
+#[must_use]
+pub fn absolute(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+/// Hands the commands to a running instance, if there is one
+pub fn forward(commands: &[Command]) -> Result<bool> {
+    forward_to(&socket_path(), commands, REPLY_TIMEOUT)
+}
+
+fn forward_to(path: &Path, commands: &[Command], timeout: Duration) -> Result<bool> {
+    let Ok(stream) = UnixStream::connect(path) else {
+        return Ok(false);
+    };
+    // A running instance that hangs must not hang every later launch too
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    for command in commands {
+        let mut line = serde_json::to_string(command)?;
+        line.push('\n');
+        let mut reply = String::new();
+        writer
+            .write_all(line.as_bytes())
+            .and_then(|()| reader.read_line(&mut reply))
+            .map_err(|err| {
+                eyre!(
+                    "Llama Desktop is already running but doesn't answer: {}",
+                    err
+                )
+            })?;
+        let reply: Reply = serde_json::from_str(&reply)?;
+        if let Some(err) = reply.error {
+            eprintln!("{}", err);
+        }
+        if let Some(status) = reply.status {
+            println!("{}", serde_json::to_string_pretty(&status)?);
+        }
+    }
+    Ok(true)
+}
+
+/// Hands the commands over once an instance that is still starting answers
+pub fn forward_when_ready(commands: &[Command]) -> Result<bool> {
+    let started = Instant::now();
+    while started.elapsed() < STARTUP_WAIT {
+        if forward(commands)? {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    Ok(false)
+}
+
+/// Takes over the socket for this instance, `None` when another one owns it
+pub fn listen() -> Result<Option<std::os::unix::net::UnixListener>> {
+    let Some((listener, lock)) = claim(&socket_path())? else {
+        return Ok(None);
+    };
+    *OWNER.write() = Some(lock);
+    Ok(Some(listener))
+}
+
+/// Binds the socket along with the lock that makes it ours
+fn claim(path: &Path) -> Result<Option<(std::os::unix::net::UnixListener, File)>> {
+    if let Some(dir) = path.parent() {
+        ensure_private(dir)?;
+    }
+    // Only the holder of the lock may replace the socket, so two launches at
+    // the same moment can't both bind
+    let lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    match lock.try_lock() {
+        Ok(()) => (),
+        Err(TryLockError::WouldBlock) => return Ok(None),
+        Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+    let listener = match std::os::unix::net::UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Ok(None);
+            }
+            // Nobody answered, so whatever is there was left behind
+            warn!("removing stale socket {:?}", path);
+            fs::remove_file(path)?;
+            std::os::unix::net::UnixListener::bind(path)?
+        }
+        Err(err) => return Err(err.into()),
+    };
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+    Ok(Some((listener, lock)))
+}
+
+/// Accepts connections until the app exits
+pub async fn serve(listener: std::os::unix::net::UnixListener) {
+    let listener = match UnixListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("error listening on socket");
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(err) = handle(stream).await {
+                        warn!("socket client: {:?}", err);
+                    }
+                });
+            }
+            Err(err) => {
+                warn!("socket accept: {:?}", err);
+            }
+        }
+    }
+}
+
+async fn handle(stream: AsyncUnixStream) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = AsyncBufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Command>(&line) {
+            Ok(command) => execute(command),
+            Err(err) => Reply {
+                error: Some(format!("invalid command: {}", err)),
+                ..Default::default()
+            },
+        };
+        writer
+            .write_all(serde_json::to_string(&reply)?.as_bytes())
+            .await?;
+        writer.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+fn execute(command: Command) -> Reply {
+    let error = match &command {
+        Command::Status => {
+            let state = STATE.read();
+            return Reply {
+                ok: true,
+                status: Some(Status {
+                    model: state
+                        .models
+                        .get(state.selected_model)
+                        .cloned()
+                        .unwrap_or_default(),
+                    title: state.title.to_owned(),
+                    path: state.path.clone(),
+                    turns: state.turns.len(),
+                    retrieving: state.retrieving,
+                    queued: state.queue.len(),
+                }),
+                ..Default::default()
+            };
+        }
+        Command::Open { path } if !PathBuf::from(path).is_file() => {
+            Some(format!("no such file: {}", path))
+        }
+        Command::Open { .. } if STATE.read().retrieving => {
+            Some("busy answering, try again later".to_string())
+        }
+        Command::Ask { prompt, .. } if prompt.trim().is_empty() => Some("empty prompt".to_string()),
+        Command::Ask {
+            model: Some(model), ..
+        } if STATE.read().model_name(model).is_none() => Some(format!("unknown model {}", model)),
+        Command::Model { name } if STATE.read().model_name(name).is_none() => {
+            Some(format!("unknown model {}", name))
+        }
+        _ => None,
+    };
+    if error.is_none() {
+        INBOX.write().push(command);
+        request_repaint();
+    }
+    Reply {
+        ok: error.is_none(),
+        error,
+        ..Default::default()
+    }
+}
+
+/// Releases the socket for the next instance, if this one owns it
+pub fn finish() {
+    let Some(lock) = OWNER.write().take() else {
+        return;
+    };
+    let path = socket_path();
+    if let Err(err) = fs::remove_file(&path) {
+        warn!("error removing {:?}: {:?}", &path, err);
+    }
+    drop(lock);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+
+    use super::*;
+
+    fn temp_socket(name: &str) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("llama-desktop-ipc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("test.sock")
+    }
+
+    #[test]
+    fn second_claim_finds_the_owner() {
+        let path = temp_socket("owner");
+        let owner = claim(&path).unwrap();
+        assert!(owner.is_some());
+        assert!(claim(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn stale_socket_is_replaced() {
+        let path = temp_socket("stale");
+        // An instance that died without removing its socket
+        drop(claim(&path).unwrap());
+        assert!(path.exists());
+        assert!(claim(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn only_one_of_simultaneous_launches_wins() {
+        let path = temp_socket("race");
+        drop(claim(&path).unwrap());
+        let barrier = Arc::new(Barrier::new(8));
+        let launches = (0..8)
+            .map(|_| {
+                let (path, barrier) = (path.clone(), Arc::clone(&barrier));
+                thread::spawn(move || {
+                    barrier.wait();
+                    claim(&path).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        let owners = launches
+            .into_iter()
+            .map(|launch| launch.join().unwrap())
+            .filter(Option::is_some)
+            .count();
+        assert_eq!(owners, 1);
+    }
+
+    #[test]
+    fn socket_folder_must_be_private() {
+        let path = temp_socket("shared");
+        let dir = path.parent().unwrap();
+        assert!(claim(&path).unwrap().is_some());
+        assert_eq!(fs::metadata(dir).unwrap().mode() & 0o777, 0o700);
+
+        // Left open by someone else
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(claim(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+        fs::write(dir, b"").unwrap();
+        assert!(claim(&path).is_err());
+        fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn stuck_instance_times_out() {
+        let path = temp_socket("stuck");
+        let (_listener, _lock) = claim(&path).unwrap().unwrap();
+        // Connected but never answering, as a frozen window would
+        let started = Instant::now();
+        let res = forward_to(&path, &[Command::Status], Duration::from_millis(200));
+        assert!(res.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn finish_leaves_another_owner_alone() {
+        // This process never claimed the real socket
+        finish();
+        assert!(OWNER.read().is_none());
+    }
+}
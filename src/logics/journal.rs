@@ -0,0 +1,161 @@
+use std::{fs, path::PathBuf};
+
+use super::context::Strategy;
+use super::conversation::Turn;
+use super::library::Meta;
+use super::paths::data_dir;
+use super::{set_model, DEFAULT_INPUT, STATE};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+const LOCK: &str = "session.lock";
+const JOURNAL: &str = "journal.json";
+
+/// Draft and conversation as they were at some point of a session
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub model: String,
+    pub title: String,
+    pub title_auto: bool,
+    pub input: String,
+    pub system: String,
+    pub turns: Vec<Turn>,
+    pub memory: String,
+    pub strategy: Strategy,
+    pub meta: Meta,
+    pub path: Option<String>,
+}
+
+impl Snapshot {
+    #[must_use]
+    pub fn take() -> Self {
+        let state = STATE.read();
+        Self {
+            model: state
+                .models
+                .get(state.selected_model)
+                .cloned()
+                .unwrap_or_default(),
+            title: state.title.to_owned(),
+            title_auto: state.title_auto,
+            input: state.input.to_owned(),
+            system: state.system.to_owned(),
+            turns: state.turns.clone(),
+            memory: state.memory.to_owned(),
+            strategy: state.strategy,
+            meta: state.meta.clone(),
+            path: state.path.clone(),
+        }
+    }
+
+    /// Nothing typed nor answered, the untouched default prompt included
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        let input = self.input.trim();
+        (input.is_empty() || input == DEFAULT_INPUT) && self.turns.is_empty()
+    }
+
+    /// Puts the snapshot back into the state
+    pub fn restore(self) {
+        if !set_model(&self.model) {
+            warn!("using current model");
+        }
+        let mut state = STATE.write();
+        state.title = self.title;
+        state.title_auto = self.title_auto;
+        state.input = self.input;
+        state.system = self.system;
+        state.turns = self.turns;
+        state.memory = self.memory;
+        state.strategy = self.strategy;
+        state.meta = self.meta;
+        state.path = self.path;
+        state.reload = true;
+    }
+}
+
+fn journal_file(name: &str) -> Option<PathBuf> {
+    Some(data_dir("journal")?.join(name))
+}
+
+/// Marks the session as running, returning what the previous one left
+/// behind if it did not exit cleanly
+#[must_use]
+pub fn start() -> Option<Snapshot> {
+    let lock = journal_file(LOCK)?;
+    let recovered = if lock.exists() {
+        warn!("previous session did not exit cleanly");
+        journal_file(JOURNAL)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str::<Snapshot>(&content).ok())
+            .filter(|snapshot| !snapshot.is_empty())
+    } else {
+        None
+    };
+    if let Err(err) = fs::write(&lock, format!("{}", std::process::id())) {
+        eprintln!("error writing {:?}: {:?}", &lock, err);
+    }
+    recovered
+}
+
+pub async fn write(snapshot: Snapshot) {
+    if let Err(err) = do_write(&snapshot) {
+        eprintln!("error writing journal: {:?}", err);
+    }
+}
+
+fn do_write(snapshot: &Snapshot) -> Result<()> {
+    let Some(path) = journal_file(JOURNAL) else {
+        return Ok(());
+    };
+    // Write aside and rename, so a crash never leaves half a journal
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, serde_json::to_string(snapshot)?)?;
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
+
+/// Clean exit, nothing to recover next time
+pub fn finish() {
+    for name in [JOURNAL, LOCK] {
+        if let Some(path) = journal_file(name) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn untouched_window_is_empty() {
+        let snapshot = Snapshot {
+            input: DEFAULT_INPUT.to_string(),
+            ..Default::default()
+        };
+        assert!(snapshot.is_empty());
+        assert!(Snapshot::default().is_empty());
+        let blank = Snapshot {
+            input: "  \n".to_string(),
+            ..Default::default()
+        };
+        assert!(blank.is_empty());
+    }
+
+    #[test]
+    fn draft_or_turns_are_worth_restoring() {
+        let draft = Snapshot {
+            input: "Why is the sea blue?".to_string(),
+            ..Default::default()
+        };
+        assert!(!draft.is_empty());
+        let answered = Snapshot {
+            input: DEFAULT_INPUT.to_string(),
+            turns: vec![Turn::new(DEFAULT_INPUT)],
+            ..Default::default()
+        };
+        assert!(!answered.is_empty());
+    }
+}
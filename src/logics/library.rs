@@ -0,0 +1,294 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{request_repaint, STATE};
+use chrono::{DateTime, Local};
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+/// Directories deeper than this below a library folder are not scanned
+const MAX_DEPTH: usize = 4;
+
+/// How a conversation is filed, kept in its header
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Meta {
+    pub folder: String,
+    pub tags: Vec<String>,
+    pub favorite: bool,
+}
+
+impl Meta {
+    /// Reads a header line, telling whether it was one of ours
+    pub fn parse_line(&mut self, line: &str) -> Result<bool> {
+        if let Some(folder) = line.strip_prefix("folder: ") {
+            self.folder = serde_json::from_str(folder)?;
+        } else if let Some(tags) = line.strip_prefix("tags: ") {
+            self.tags = serde_json::from_str(tags)?;
+        } else if let Some(favorite) = line.strip_prefix("favorite: ") {
+            self.favorite = favorite.trim() == "true";
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Header lines, leaving out whatever is unset
+    #[must_use]
+    pub fn header(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.folder.is_empty() {
+            lines.push(format!(
+                "folder: {}",
+                serde_json::to_string(&self.folder).unwrap_or_default()
+            ));
+        }
+        if !self.tags.is_empty() {
+            lines.push(format!(
+                "tags: {}",
+                serde_json::to_string(&self.tags).unwrap_or_default()
+            ));
+        }
+        if self.favorite {
+            lines.push("favorite: true".to_string());
+        }
+        lines
+    }
+
+    /// Sets the tags from a comma separated list
+    pub fn set_tags(&mut self, text: &str) {
+        self.tags = Vec::new();
+        for tag in text.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if !self.tags.iter().any(|e| e == tag) {
+                self.tags.push(tag.to_owned());
+            }
+        }
+    }
+}
+
+/// A conversation file found in the library
+#[derive(Clone, Debug, Default)]
+pub struct Entry {
+    pub path: String,
+    pub title: String,
+    pub model: String,
+    pub date: Option<DateTime<Local>>,
+    pub meta: Meta,
+}
+
+#[derive(Debug, Default)]
+pub struct Library {
+    pub entries: Vec<Entry>,
+    pub scanning: bool,
+}
+
+#[dynamic]
+pub static mut LIBRARY: Library = Library::default();
+
+/// Reads the header of a conversation file, ignoring the turns
+pub fn read_entry(path: &Path) -> Result<Entry> {
+    let content = fs::read_to_string(path)?;
+    let mut entry = Entry {
+        path: path.to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    for line in content.lines() {
+        if line == "-----" {
+            break;
+        }
+        if entry.meta.parse_line(line)? {
+            continue;
+        }
+        if let Some(title) = line.strip_prefix("title: ") {
+            entry.title = serde_json::from_str(title)?;
+        } else if let Some(model) = line.strip_prefix("model: ") {
+            entry.model = model.to_owned();
+        } else if let Some(date) = line.strip_prefix("date: ") {
+            entry.date = DateTime::parse_from_rfc3339(date.trim())
+                .ok()
+                .map(|date| date.with_timezone(&Local));
+        }
+    }
+    if entry.date.is_none() {
+        entry.date = fs::metadata(path)?.modified().ok().map(DateTime::from);
+    }
+    if entry.title.is_empty() {
+        entry.title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+    Ok(entry)
+}
+
+fn collect(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        warn!("cannot read {:?}", dir);
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if path.is_dir() && !hidden && depth < MAX_DEPTH {
+            collect(&path, depth + 1, found);
+        } else if path.extension().and_then(|e| e.to_str()) == Some("ctx") {
+            found.push(path);
+        }
+    }
+}
+
+/// Looks for conversations in the given folders and among the recent files
+pub async fn scan(roots: Vec<String>) {
+    LIBRARY.write().scanning = true;
+    let mut paths = Vec::new();
+    for root in roots.iter() {
+        collect(Path::new(root), 0, &mut paths);
+    }
+    paths.extend(STATE.read().recent.iter().map(PathBuf::from));
+
+    let mut entries: Vec<Entry> = Vec::new();
+    for path in paths {
+        let name = path.to_string_lossy().to_string();
+        if entries.iter().any(|entry| entry.path == name) {
+            continue;
+        }
+        match read_entry(&path) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => {
+                warn!("skipping {:?}: {:?}", &path, err);
+            }
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.date));
+
+    let mut library = LIBRARY.write();
+    library.entries = entries;
+    library.scanning = false;
+    drop(library);
+    request_repaint();
+}
+
+/// Rewrites the filing lines in the header of a conversation file
+pub fn update_meta(path: &str, meta: &Meta) -> Result<()> {
+    let content = fs::read_to_string(path)?;
+    let content = with_meta(&content, meta).ok_or(eyre!("{} is not a conversation file", path))?;
+    fs::write(path, content)?;
+
+    if let Some(entry) = LIBRARY
+        .write()
+        .entries
+        .iter_mut()
+        .find(|entry| entry.path == path)
+    {
+        entry.meta = meta.clone();
+    }
+    let mut state = STATE.write();
+    if state.path.as_deref() == Some(path) {
+        state.meta = meta.clone();
+    }
+    Ok(())
+}
+
+/// Content with the filing lines of its header replaced, `None` without a
+/// header; the header ends at the first rule, as when loading the file
+#[must_use]
+fn with_meta(content: &str, meta: &Meta) -> Option<String> {
+    let mut pos = 0;
+    let (header, rule) = content.split_inclusive('\n').find_map(|line| {
+        let start = pos;
+        pos += line.len();
+        (line.trim_end_matches(['\r', '\n']) == "-----").then_some((&content[..start], start))
+    })?;
+    let newline = if content[rule..].starts_with("-----\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut res = String::new();
+    for line in header.lines() {
+        // Unreadable filing lines are replaced too
+        if matches!(Meta::default().parse_line(line), Ok(false)) {
+            res.push_str(line);
+            res.push_str(newline);
+        }
+    }
+    for line in meta.header() {
+        res.push_str(&line);
+        res.push_str(newline);
+    }
+    res.push_str(&content[rule..]);
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(folder: &str, tags: &[&str], favorite: bool) -> Meta {
+        Meta {
+            folder: folder.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            favorite,
+        }
+    }
+
+    #[test]
+    fn header_lines_round_trip() {
+        let filed = meta("Work/Rust", &["async", "tokio \"rt\""], true);
+        let mut read = Meta::default();
+        for line in filed.header() {
+            assert!(read.parse_line(&line).unwrap());
+        }
+        assert_eq!(read, filed);
+        assert!(Meta::default().header().is_empty());
+        assert!(!read.parse_line("title: \"Sky\"").unwrap());
+        assert!(read.parse_line("tags: [oops").is_err());
+    }
+
+    #[test]
+    fn rewrite_keeps_the_rest_of_the_file() {
+        let content = "title: \"Sky\"\nfolder: \"Old\"\nmodel: phi3\ntags: [\"a\"]\n-----\n> Why?\n\nBlue.\n-----\n";
+        let res = with_meta(content, &meta("New", &[], true)).unwrap();
+        assert_eq!(
+            res,
+            "title: \"Sky\"\nmodel: phi3\nfolder: \"New\"\nfavorite: true\n-----\n> Why?\n\nBlue.\n-----\n"
+        );
+        // Unfiling leaves only the other header lines
+        let res = with_meta(&res, &Meta::default()).unwrap();
+        assert_eq!(
+            res,
+            "title: \"Sky\"\nmodel: phi3\n-----\n> Why?\n\nBlue.\n-----\n"
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_crlf_and_bad_lines_go() {
+        let content = "model: phi3\r\ntags: [oops\r\n-----\r\n> Why?\r\n";
+        let res = with_meta(content, &meta("", &["sky"], false)).unwrap();
+        assert_eq!(res, "model: phi3\r\ntags: [\"sky\"]\r\n-----\r\n> Why?\r\n");
+    }
+
+    #[test]
+    fn rewrite_needs_a_header() {
+        assert!(with_meta("> Why?\n\nBlue.\n", &Meta::default()).is_none());
+        assert_eq!(
+            with_meta("-----\n> Why?\n", &meta("", &[], true)).as_deref(),
+            Some("favorite: true\n-----\n> Why?\n")
+        );
+    }
+
+    #[test]
+    fn updated_file_reads_back() {
+        let path = std::env::temp_dir().join(format!("llama-meta-{}.ctx", std::process::id()));
+        fs::write(&path, "title: \"Sky\"\n-----\n> Why?\n\nBlue.\n-----\n").unwrap();
+        let name = path.to_string_lossy().to_string();
+        let filed = meta("Science", &["sky"], true);
+        update_meta(&name, &filed).unwrap();
+        let entry = read_entry(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(entry.meta, filed);
+        assert_eq!(entry.title, "Sky");
+    }
+}
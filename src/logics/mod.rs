@@ -1,9 +1,24 @@
+pub mod compare;
+pub mod context;
+pub mod conversation;
+pub mod desktop;
+pub mod history;
+pub mod ipc;
+pub mod journal;
+pub mod library;
+pub mod models;
+pub mod notify;
+pub mod paths;
+mod repaint;
 mod sender;
 mod state;
 pub mod storage;
+pub mod templates;
 mod timeouts;
+pub mod titles;
 mod version;
 
+pub use self::repaint::*;
 pub use self::sender::*;
 pub use self::state::*;
 pub use self::timeouts::*;
@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use super::{request_repaint, STATE, TIMEOUTS};
+use crate::ollama;
+use crate::protocol::{AIModel, AdditionalParams, Request, Response};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+/// How often the model list is read again, to notice pulls and removals
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keep-alive choices offered for each model, as Ollama reads them
+pub const KEEP_ALIVES: [&str; 6] = ["0", "5m", "30m", "1h", "4h", "-1m"];
+
+#[must_use]
+pub fn keep_alive_label(keep_alive: &str) -> String {
+    match keep_alive {
+        "0" => "unload right away".to_string(),
+        "-1m" => "forever".to_string(),
+        other => other.to_owned(),
+    }
+}
+
+/// What to select when the remembered model is not installed any more
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fallback {
+    /// Another tag of the same model, or else one of the same family
+    #[default]
+    Family,
+    Recent,
+    Ask,
+}
+
+impl Fallback {
+    pub const ALL: [Fallback; 3] = [Fallback::Family, Fallback::Recent, Fallback::Ask];
+
+    #[must_use]
+    pub fn label(&self) -> &'static str {
+        match self {
+            Fallback::Family => "Same family",
+            Fallback::Recent => "Most recent",
+            Fallback::Ask => "Ask me",
+        }
+    }
+}
+
+/// Name without the tag, `llama3.1` for `llama3.1:8b`
+#[must_use]
+fn base_name(name: &str) -> &str {
+    name.split_once(':').map(|(base, _)| base).unwrap_or(name)
+}
+
+/// Position of the model, or of its replacement; `None` when the user must choose
+#[must_use]
+pub fn resolve(catalog: &[AIModel], name: &str, family: &str, fallback: Fallback) -> Option<usize> {
+    if let Some(idx) = catalog.iter().position(|model| model.name == name) {
+        return Some(idx);
+    }
+    // The catalog is sorted by date, so the first match is the most recent
+    match fallback {
+        _ if name.is_empty() => Some(0),
+        Fallback::Family => catalog
+            .iter()
+            .position(|model| base_name(&model.name) == base_name(name))
+            .or_else(|| {
+                catalog
+                    .iter()
+                    .position(|model| !family.is_empty() && model.details.family == family)
+            })
+            .or(Some(0)),
+        Fallback::Recent => Some(0),
+        Fallback::Ask => None,
+    }
+}
+
+/// Selects a model by name, falling back as configured when it is gone
+pub fn select(name: &str, family: &str) {
+    let mut state = STATE.write();
+    match resolve(&state.catalog, name, family, state.fallback) {
+        Some(idx) => {
+            match state.models.get(idx) {
+                Some(model) if !name.is_empty() && model != name => {
+                    warn!("model {} not found, using {}", name, model);
+                }
+                _ => (),
+            }
+            if state.missing_model.as_deref() == Some(name) {
+                state.missing_model = None;
+            }
+            state.selected_model = idx;
+        }
+        None => {
+            warn!("model {} not found, asking", name);
+            state.selected_model = 0;
+            state.missing_model = Some(name.to_owned());
+        }
+    }
+}
+
+/// Installs a new model list, keeping the selected model by name
+pub fn set_catalog(catalog: Vec<AIModel>) {
+    let (name, family) = {
+        let state = STATE.read();
+        match state.catalog.get(state.selected_model) {
+            Some(model) => (model.name.to_owned(), model.details.family.to_owned()),
+            None => Default::default(),
+        }
+    };
+    let mut state = STATE.write();
+    state.models = catalog.iter().map(|model| model.name.to_owned()).collect();
+    state.catalog = catalog;
+    drop(state);
+    select(&name, &family);
+}
+
+/// Reads the model list again, keeping the current one if Ollama can't be reached
+pub async fn refresh() {
+    let catalog = match ollama::list_models().await {
+        Ok(catalog) if catalog.is_empty() => {
+            warn!("Ollama lists no models, keeping the previous list");
+            return;
+        }
+        Ok(catalog) => catalog,
+        Err(err) => {
+            warn!("error listing models: {:?}", err);
+            return;
+        }
+    };
+    if catalog == STATE.read().catalog {
+        return;
+    }
+    warn!("model list changed");
+    set_catalog(catalog);
+    request_repaint();
+}
+
+/// Reads which models Ollama holds in memory
+pub async fn refresh_loaded() {
+    match ollama::list_loaded().await {
+        Ok(loaded) => {
+            if loaded != STATE.read().loaded {
+                STATE.write().loaded = loaded;
+                request_repaint();
+            }
+        }
+        Err(err) => {
+            warn!("error listing loaded models: {:?}", err);
+        }
+    }
+}
+
+/// Keeps the model lists up to date until the app exits
+pub async fn watch() {
+    refresh_loaded().await;
+    loop {
+        time::sleep(REFRESH_INTERVAL).await;
+        refresh().await;
+        refresh_loaded().await;
+    }
+}
+
+/// Loads the model with an empty request, so the first answer starts at once
+pub async fn preload(model: String) {
+    warn!("preloading {}", &model);
+    let (keep_alive, num_ctx) = {
+        let state = STATE.read();
+        (state.keep_alive(&model), state.num_ctx)
+    };
+    // Ollama loads the model again when the window size changes
+    if let Err(err) = load(&model, keep_alive, Some(num_ctx)).await {
+        warn!("error preloading {}: {:?}", &model, err);
+    }
+    refresh_loaded().await;
+}
+
+/// Frees the memory the model holds
+pub async fn unload(model: String) {
+    warn!("unloading {}", &model);
+    // Without options, so a runner with another window size isn't loaded first
+    if let Err(err) = load(&model, Some("0".to_string()), None).await {
+        warn!("error unloading {}: {:?}", &model, err);
+    }
+    refresh_loaded().await;
+}
+
+async fn load(model: &str, keep_alive: Option<String>, num_ctx: Option<usize>) -> Result<()> {
+    let payload = load_request(model, keep_alive, num_ctx);
+    let timeout = Duration::from_secs(TIMEOUTS[STATE.read().timeout_idx] as u64);
+    debug!(&payload);
+    ollama::stream("/api/generate", &payload, timeout, |_: Response| Ok(())).await
+}
+
+/// Empty request that only loads or unloads the model
+#[must_use]
+fn load_request(model: &str, keep_alive: Option<String>, num_ctx: Option<usize>) -> Request {
+    Request {
+        model: model.to_owned(),
+        prompt: String::new(),
+        system: None,
+        think: None,
+        keep_alive,
+        stream: false,
+        options: num_ctx.map(|num_ctx| AdditionalParams {
+            num_ctx,
+            ..Default::default()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ModelDetails;
+
+    fn model(name: &str, family: &str) -> AIModel {
+        AIModel {
+            name: name.to_string(),
+            modified_at: String::new(),
+            size: 0,
+            details: ModelDetails {
+                family: family.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Most recent first, as `ollama::list_models` sorts them
+    fn catalog() -> Vec<AIModel> {
+        vec![
+            model("mistral:latest", "llama"),
+            model("qwen2.5-coder:14b", "qwen2"),
+            model("llama3.1:8b", "llama"),
+            model("llama3.1:70b", "llama"),
+        ]
+    }
+
+    #[test]
+    fn exact_match() {
+        for fallback in Fallback::ALL {
+            assert_eq!(
+                resolve(&catalog(), "llama3.1:70b", "llama", fallback),
+                Some(3)
+            );
+        }
+    }
+
+    #[test]
+    fn another_tag_of_the_same_model() {
+        let found = resolve(&catalog(), "llama3.1:latest", "llama", Fallback::Family);
+        assert_eq!(found, Some(2));
+    }
+
+    #[test]
+    fn same_family() {
+        let found = resolve(&catalog(), "qwen2.5:7b", "qwen2", Fallback::Family);
+        assert_eq!(found, Some(1));
+        // Nothing alike, the most recent one
+        let found = resolve(&catalog(), "phi3:mini", "phi3", Fallback::Family);
+        assert_eq!(found, Some(0));
+        let found = resolve(&catalog(), "phi3:mini", "", Fallback::Family);
+        assert_eq!(found, Some(0));
+    }
+
+    #[test]
+    fn most_recent() {
+        let found = resolve(&catalog(), "llama3.1:latest", "llama", Fallback::Recent);
+        assert_eq!(found, Some(0));
+    }
+
+    #[test]
+    fn ask() {
+        let found = resolve(&catalog(), "llama3.1:latest", "llama", Fallback::Ask);
+        assert_eq!(found, None);
+    }
+
+    #[test]
+    fn nothing_remembered() {
+        for fallback in Fallback::ALL {
+            assert_eq!(resolve(&catalog(), "", "", fallback), Some(0));
+        }
+    }
+
+    #[test]
+    fn unloading_sends_no_options() {
+        let unload =
+            serde_json::to_value(load_request("phi3", Some("0".to_string()), None)).unwrap();
+        assert_eq!(unload["keep_alive"], "0");
+        assert!(unload.get("options").is_none());
+        let preload = serde_json::to_value(load_request("phi3", None, Some(4096))).unwrap();
+        assert_eq!(preload["options"]["num_ctx"], 4096);
+        assert!(preload.get("keep_alive").is_none());
+    }
+}
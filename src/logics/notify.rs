@@ -0,0 +1,204 @@
+use std::{collections::HashMap, time::Duration};
+
+use super::paths::APP_ID;
+use eyre::Result;
+use tokio::time;
+use zbus::{zvariant::Value, Connection};
+
+const MAX_BODY: usize = 200;
+/// Gives up on a session bus that doesn't answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a desktop notification says
+#[derive(Debug, PartialEq)]
+pub struct Notification {
+    pub summary: &'static str,
+    pub body: String,
+}
+
+/// Notification for a finished answer, `None` when the user is watching,
+/// more prompts are coming or notifications are off
+#[must_use]
+pub fn finished(
+    outcome: Result<&str, &str>,
+    enabled: bool,
+    background: bool,
+    pending: bool,
+) -> Option<Notification> {
+    if !enabled || !background || pending {
+        return None;
+    }
+    Some(match outcome {
+        Ok(answer) => Notification {
+            summary: "Answer ready",
+            body: shorten(answer),
+        },
+        Err(err) => Notification {
+            summary: "Generation failed",
+            body: shorten(err),
+        },
+    })
+}
+
+/// Trimmed body, cut at `MAX_BODY` characters
+#[must_use]
+fn shorten(body: &str) -> String {
+    let mut body = body.trim().to_string();
+    if let Some((idx, _)) = body.char_indices().nth(MAX_BODY) {
+        body.truncate(idx);
+        body.push('…');
+    }
+    body
+}
+
+/// Shows a desktop notification through the freedesktop D-Bus interface
+pub async fn notify(notification: Notification) {
+    match time::timeout(TIMEOUT, do_notify(&notification)).await {
+        Ok(Ok(())) => (),
+        Ok(Err(err)) => eprintln!("error sending notification: {:?}", err),
+        Err(_) => eprintln!("error sending notification: session bus timed out"),
+    }
+}
+
+async fn do_notify(notification: &Notification) -> Result<()> {
+    let connection = Connection::session().await?;
+    send(&connection, notification).await
+}
+
+async fn send(connection: &Connection, notification: &Notification) -> Result<()> {
+    connection
+        .call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            &(
+                APP_ID,
+                0u32,
+                APP_ID,
+                notification.summary,
+                &notification.body,
+                Vec::<&str>::new(),
+                HashMap::<&str, Value>::new(),
+                -1i32,
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use zbus::zvariant::OwnedValue;
+
+    #[test]
+    fn no_notification_while_watching() {
+        assert_eq!(finished(Ok("done"), true, false, false), None);
+        assert_eq!(finished(Ok("done"), false, true, false), None);
+        assert_eq!(finished(Ok("done"), true, true, true), None);
+    }
+
+    #[test]
+    fn answer_and_failure() {
+        assert_eq!(
+            finished(Ok("  The sky is blue.\n"), true, true, false),
+            Some(Notification {
+                summary: "Answer ready",
+                body: "The sky is blue.".to_string(),
+            })
+        );
+        assert_eq!(
+            finished(Err("timed out"), true, true, false).map(|e| e.summary),
+            Some("Generation failed")
+        );
+    }
+
+    #[test]
+    fn long_body_is_cut_on_characters() {
+        let body = shorten(&"é".repeat(MAX_BODY + 10));
+        assert_eq!(body.chars().count(), MAX_BODY + 1);
+        assert!(body.ends_with('…'));
+        assert_eq!(shorten(&"a".repeat(MAX_BODY)), "a".repeat(MAX_BODY));
+    }
+
+    /// Stands in for the desktop's notification daemon
+    struct Daemon(Arc<Mutex<Vec<(String, String)>>>);
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl Daemon {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            _app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<String>,
+            _hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut received = self.0.lock().unwrap();
+            received.push((summary.to_owned(), body.to_owned()));
+            received.len() as u32
+        }
+    }
+
+    struct Bus(Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+        }
+    }
+
+    /// Private session bus and its address
+    fn session_bus() -> (Bus, String) {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("dbus-daemon must be installed");
+        let mut address = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        (Bus(child), address.trim().to_owned())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    async fn sends_through_a_local_session_bus() {
+        let (_bus, address) = session_bus();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let _daemon = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.Notifications")
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/Notifications",
+                Daemon(Arc::clone(&received)),
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let connection = zbus::connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let notification = finished(Ok("The sky is blue."), true, true, false).unwrap();
+        send(&connection, &notification).await.unwrap();
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("Answer ready".to_string(), "The sky is blue.".to_string())]
+        );
+    }
+}
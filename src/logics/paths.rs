@@ -0,0 +1,14 @@
+use std::{fs, path::PathBuf};
+
+pub const APP_ID: &str = "llama-desktop";
+
+/// Application data directory, created on demand
+#[must_use]
+pub fn data_dir(sub: &str) -> Option<PathBuf> {
+    let path = eframe::storage_dir(APP_ID)?.join(sub);
+    if let Err(err) = fs::create_dir_all(&path) {
+        eprintln!("error creating {:?}: {:?}", &path, err);
+        return None;
+    }
+    Some(path)
+}
@@ -0,0 +1,16 @@
+/// Wakes the UI up after background tasks change the state
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+#[dynamic]
+static mut WAKER: Option<Waker> = None;
+
+pub fn set_waker(waker: impl Fn() + Send + Sync + 'static) {
+    *WAKER.write() = Some(Box::new(waker));
+}
+
+/// Asks for a new frame, the UI only repaints on input otherwise
+pub fn request_repaint() {
+    if let Some(waker) = WAKER.read().as_ref() {
+        waker();
+    }
+}
@@ -1,127 +1,167 @@
-use std::borrow::Borrow;
-
+use super::context::{self, Strategy};
+use super::conversation::{ThinkSplitter, Turn};
+use super::history;
+use super::notify;
+use super::repaint::request_repaint;
 use super::state::STATE;
 use super::timeouts::TIMEOUTS;
-use crate::helpers::{format_input_to_output, HR};
+use super::titles;
 use crate::ollama;
-use crate::protocol::{AdditionalParams, Request, Response};
+use crate::protocol::{AdditionalParams, ChatRequest, ChatResponse};
 use eyre::{eyre, Result};
-use reqwest::header;
 use tokio::time;
 
+/// Temperature, and the prompt when it comes from the queue instead of the input
 #[derive(Debug)]
-pub struct Sender(f32);
+pub struct Sender(f32, Option<String>);
 
 impl Drop for Sender {
     fn drop(&mut self) {
         warn!("FINISHED");
         let mut state = STATE.write();
-        state.output.push_str(HR);
         state.retrieving = false;
         state.escape = false;
         state.reload = true;
+        if let Some(turn) = state.turns.last_mut() {
+            turn.finish_thinking();
+        }
+        drop(state);
+        request_repaint();
     }
 }
 
 impl Sender {
     #[inline]
     pub fn new(temperature: f32) -> Self {
-        Self(temperature)
+        // Set right away, so nothing else starts before the task runs
+        STATE.write().retrieving = true;
+        Self(temperature, None)
     }
 
-    pub async fn send(self) {
+    #[inline]
+    pub fn queued(temperature: f32, prompt: String) -> Self {
         STATE.write().retrieving = true;
+        Self(temperature, Some(prompt))
+    }
 
-        if let Err(err) = self.do_send().await {
-            let mut state = STATE.write();
+    pub async fn send(self) {
+        let result = self.do_send().await;
+        if result.is_ok() && STATE.read().title_auto {
+            tokio::spawn(titles::generate());
+        }
+        if let Err(err) = result.as_ref() {
             warn!("{:?}", err);
-            state.output.push_str("\n## ERROR:\n");
-            state.output.push_str(&err.to_string());
+            let mut state = STATE.write();
+            state.push_error(err);
+            // Don't run the rest of the queue on top of a failure
+            state.queue_paused = true;
+        }
+
+        let background = !STATE.read().focused;
+        let pending = !STATE.read().queue.is_empty() && !STATE.read().queue_paused;
+        if background && !pending {
+            STATE.write().unread = true;
+        }
+        let answer = STATE
+            .read()
+            .turns
+            .last()
+            .map(|turn| turn.answer.to_owned())
+            .unwrap_or_default();
+        let error = result.err().map(|err| err.to_string());
+        let outcome = match error.as_deref() {
+            Some(err) => Err(err),
+            None => Ok(answer.as_str()),
+        };
+        let notification =
+            notify::finished(outcome, STATE.read().notifications, background, pending);
+        // Finished before notifying, so a slow session bus can't keep the app busy
+        drop(self);
+        if let Some(notification) = notification {
+            tokio::spawn(notify::notify(notification));
         }
     }
 
     async fn do_send(&self) -> Result<()> {
         warn!("SENDING CONTENT");
 
-        let context = STATE.read().context.clone();
-        debug!(&context);
-
-        let input = STATE.read().input.to_owned();
+        let input = match self.1.as_ref() {
+            Some(prompt) => prompt.to_owned(),
+            None => STATE.read().input.to_owned(),
+        };
         debug!(&input);
 
         if input.is_empty() {
             return Err(eyre!("empty question"));
         }
 
-        if STATE.read().title.is_empty() {
-            STATE.write().title = input.to_owned();
+        titles::start(&input);
+
+        STATE.write().turns.push(Turn::new(&input));
+        if self.1.is_none() {
+            STATE.write().input.clear();
         }
+        history::record(&input);
 
-        STATE
-            .write()
-            .output
-            .push_str(&format_input_to_output(&input));
-        STATE.write().output.push_str("\n\n");
-        if STATE.read().output.is_empty() {
-            // XXX: WORKAROUND! It should never happen!
-            return Err(eyre!("couldn't access output frame"));
+        let upto = STATE.read().turns.len() - 1;
+        let summarize = {
+            let state = STATE.read();
+            state.strategy == Strategy::Summarize && context::overflows(&state, upto, &input)
+        };
+        if summarize {
+            // Dropping the oldest turns still applies if this fails
+            if let Err(err) = context::summarize(upto, self.0).await {
+                warn!("summarizing failed: {:?}", err);
+            }
+            self.check_escape()?;
         }
-        STATE.write().input.clear();
-
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "Content-Type",
-            header::HeaderValue::from_static("application/json"),
-        );
-        debug!(&headers);
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()?;
+
         let mut payload = {
             let state = STATE.read();
-            Request {
-                model: state.models[state.selected_model].to_owned(),
-                prompt: input,
+            let plan = context::plan(&state, upto, &input);
+            if !plan.dropped.is_empty() {
+                warn!("{} turns left out of the context", plan.dropped.len());
+            }
+            let model = state.models[state.selected_model].to_owned();
+            ChatRequest {
+                keep_alive: state.keep_alive(&model),
+                model,
+                messages: plan.messages,
+                think: state.think,
                 stream: true,
-                options: AdditionalParams::default(),
-                context: if context.is_empty() {
-                    None
-                } else {
-                    Some(context)
+                options: AdditionalParams {
+                    num_ctx: state.num_ctx,
+                    ..Default::default()
                 },
             }
         };
         payload.options.temperature = self.0;
         debug!(&payload);
-        let payload = serde_json::to_string(&payload)?;
-        let uri = ollama::path("/api/generate");
-        debug!(&uri);
         let timeout = time::Duration::from_secs(TIMEOUTS[STATE.read().timeout_idx] as u64);
         debug!(&timeout);
 
         self.check_escape()?;
-        let mut response = time::timeout(timeout, client.post(uri).body(payload).send()).await??;
-        if !response.status().is_success() {
-            return Err(eyre!(response.text().await?));
-        }
-
-        debug!(&response);
-        self.check_escape()?;
-        'read: while let Some(current) = time::timeout(timeout, response.chunk()).await?? {
-            debug!(&current);
+        let mut splitter = ThinkSplitter::default();
+        ollama::stream("/api/chat", &payload, timeout, |chunk: ChatResponse| {
             self.check_escape()?;
-            let chunk: Response = serde_json::from_str(
-                std::str::from_utf8(current.borrow())?
-            )?;
             let mut state = STATE.write();
-            state.output.push_str(&chunk.response);
-            if let Some(context) = chunk.context {
-                state.context = context.clone();
+            if let Some(turn) = state.turns.last_mut() {
+                if let Some(message) = chunk.message.as_ref() {
+                    if let Some(thinking) = message.thinking.as_deref() {
+                        turn.push_thinking(thinking);
+                    }
+                    splitter.feed(turn, &message.content);
+                }
+                if chunk.done {
+                    splitter.flush(turn);
+                    turn.answer_tokens = chunk.eval_count.map(|count| count as u32);
+                }
             }
-            if chunk.done {
-                break 'read;
-            }
-        }
+            drop(state);
+            request_repaint();
+            Ok(())
+        })
+        .await?;
 
         warn!("DONE");
         Ok(())
@@ -1,33 +1,125 @@
-#[derive(Debug)]
+use std::{collections::BTreeMap, fmt::Display};
+
+use super::context::Strategy;
+use super::conversation::Turn;
+use super::library::Meta;
+use super::models::Fallback;
+use super::templates::Template;
+use crate::protocol::{AIModel, LoadedModel};
+
+/// Prompt the input box starts with
+pub const DEFAULT_INPUT: &str = "Why the sky is blue?";
+
+#[derive(Debug, Default)]
 pub struct State {
     pub title: String,
+    /// The title is a stand-in the model may still replace
+    pub title_auto: bool,
     pub models: Vec<String>,
+    /// Details of `models`, in the same order
+    pub catalog: Vec<AIModel>,
     pub selected_model: usize,
+    pub fallback: Fallback,
+    /// Models pinned at the top of the picker
+    pub favorites: Vec<String>,
+    /// Short names standing for models, such as `coder`
+    pub aliases: BTreeMap<String, String>,
+    /// Model that went missing, waiting for the user to pick another
+    pub missing_model: Option<String>,
+    /// How long each model stays loaded after a request, such as `30m`
+    pub keep_alive: BTreeMap<String, String>,
+    /// Load the model as soon as it is selected
+    pub preload: bool,
+    /// Models Ollama holds in memory
+    pub loaded: Vec<LoadedModel>,
     pub input: String,
-    pub output: String,
+    pub turns: Vec<Turn>,
     pub retrieving: bool,
     pub reload: bool,
     pub timeout_idx: usize,
     pub escape: bool,
-    pub context: Vec<u32>,
+    /// Size of the context window asked from the model
+    pub num_ctx: usize,
+    /// What to do when the conversation outgrows the window
+    pub strategy: Strategy,
+    /// Summary standing in for the summarized turns
+    pub memory: String,
+    /// Folder, tags and star of the conversation
+    pub meta: Meta,
     pub cwd: String,
+    pub system: String,
+    pub templates: Vec<Template>,
+    pub recent: Vec<String>,
+    /// Prompts sent in any conversation, oldest first
+    pub history: Vec<String>,
+    /// File the conversation was loaded from or saved to
+    pub path: Option<String>,
+    /// Whether to ask the model to think, `None` leaves it to the model
+    pub think: Option<bool>,
+    pub export_thinking: bool,
+    /// Notify the desktop when an answer finishes in the background
+    pub notifications: bool,
+    pub focused: bool,
+    /// An answer finished while the window was not focused
+    pub unread: bool,
+    /// Prompts waiting for the current answer to finish
+    pub queue: Vec<String>,
+    pub queue_paused: bool,
 }
 
 impl State {
     pub fn reset(&mut self) {
         warn!("RESETTING STATE");
-        self.input = "Why the sky is blue?".to_owned();
+        self.input = DEFAULT_INPUT.to_owned();
         self.title = String::new();
-        self.output = String::new();
+        self.title_auto = false;
+        self.turns = Vec::new();
         self.retrieving = false;
         self.reload = true;
-        self.context = Vec::new();
+        self.strategy = Strategy::default();
+        self.memory = String::new();
+        self.meta = Meta::default();
+        self.system = String::new();
+        self.path = None;
+        self.queue.clear();
+        self.queue_paused = false;
         debug!(self);
     }
+
+    /// Installed model with this name or alias
+    #[must_use]
+    pub fn model_name(&self, name: &str) -> Option<String> {
+        if self.models.iter().any(|model| model == name) {
+            return Some(name.to_owned());
+        }
+        self.aliases
+            .get(name)
+            .filter(|model| self.models.contains(model))
+            .cloned()
+    }
+
+    /// Keep-alive to send along with requests to this model
+    #[must_use]
+    pub fn keep_alive(&self, model: &str) -> Option<String> {
+        self.keep_alive.get(model).cloned()
+    }
+
+    /// Marks the last answer as failed, kept apart so it isn't sent back to the model
+    pub fn push_error(&mut self, err: impl Display) {
+        let message = err.to_string();
+        match self.turns.last_mut() {
+            Some(turn) => turn.error = Some(message),
+            None => self.turns.push(Turn {
+                error: Some(message),
+                ..Default::default()
+            }),
+        }
+    }
 }
 
 pub fn set_model(model: impl ToString) -> bool {
     let model = model.to_string();
+    let model = STATE.read().model_name(&model).unwrap_or(model);
     warn!("setting model to {}", &model);
     let models = STATE.read().models.clone();
     for (idx, model_) in models.iter().enumerate() {
@@ -45,14 +137,42 @@ pub fn set_model(model: impl ToString) -> bool {
 #[dynamic]
 pub static mut STATE: State = State {
     title: String::new(),
+    title_auto: false,
     models: Vec::new(),
+    catalog: Vec::new(),
     selected_model: usize::MAX,
-    input: "Why the sky is blue?".to_owned(),
-    output: String::new(),
+    fallback: Fallback::Family,
+    favorites: Vec::new(),
+    aliases: BTreeMap::new(),
+    missing_model: None,
+    keep_alive: BTreeMap::new(),
+    preload: true,
+    loaded: Vec::new(),
+    input: DEFAULT_INPUT.to_owned(),
+    turns: Vec::new(),
     retrieving: false,
     reload: true,
     escape: false,
     timeout_idx: usize::MAX,
-    context: Vec::new(),
+    num_ctx: 8192,
+    strategy: Strategy::DropOldest,
+    memory: String::new(),
+    meta: Meta {
+        folder: String::new(),
+        tags: Vec::new(),
+        favorite: false,
+    },
     cwd: String::new(),
+    system: String::new(),
+    templates: Vec::new(),
+    recent: Vec::new(),
+    history: Vec::new(),
+    path: None,
+    think: None,
+    export_thinking: true,
+    notifications: true,
+    focused: true,
+    unread: false,
+    queue: Vec::new(),
+    queue_paused: false,
 };
@@ -1,6 +1,13 @@
-use std::{fs::File, path::PathBuf};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use super::{set_model, STATE };
+use super::context::Strategy;
+use super::conversation::{self, Thinking, Turn};
+use super::ipc::{Command, INBOX};
+use super::titles;
+use super::{request_repaint, set_model, STATE, VERSION};
 use chrono::Local;
 use comrak::{markdown_to_html, Options};
 use eyre::{eyre, Result};
@@ -14,18 +21,40 @@ enum Step {
 
 use Step::*;
 
+const MAX_RECENT: usize = 20;
+
 #[derive(Debug, Default)]
-struct Parser(String, Vec<u32>);
+struct Parser(String);
 
-pub async fn save_content(content: impl ToString) {
-    let content = content.to_string();
+pub async fn save_snippet(code: String, extension: String) {
     let cwd = STATE.read().cwd.to_owned();
+    if let Some(path) = FileDialog::new()
+        .set_title("Llama Desktop Save Code")
+        .set_directory(cwd)
+        .set_file_name(format!("snippet.{}", extension))
+        .save_file()
+    {
+        warn!("saving code to {:?}", &path);
+        if let Err(err) = std::fs::write(&path, code) {
+            eprintln!("error saving to {:?}", &path);
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+pub async fn save_content(turns: Vec<Turn>) {
+    let include_thinking = STATE.read().export_thinking;
+    let cwd = STATE.read().cwd.to_owned();
+    let name = match titles::file_stem(&STATE.read().title) {
+        Some(stem) => format!("{}.ctx", stem),
+        None => Local::now().format("%Y-%m-%d-%H%M.ctx").to_string(),
+    };
     if let Some(path) = FileDialog::new()
         .set_title("Llama Desktop Save Context")
         .set_directory(cwd)
         .add_filter("Context", &["ctx"])
         .add_filter("HTML (not reloadable)", &["html", "htm"])
-        .set_file_name(Local::now().format("%Y-%m-%d-%H%M.ctx").to_string())
+        .set_file_name(name)
         .save_file()
     {
         if let Err(err) = {
@@ -39,19 +68,34 @@ pub async fn save_content(content: impl ToString) {
                     STATE.write().cwd = parent.to_owned();
                 }
                 STATE.write().reload = true;
-                save_context(&content, path.as_path().to_str().unwrap()).await
+                let thinking = if include_thinking {
+                    Thinking::Tagged
+                } else {
+                    Thinking::Omit
+                };
+                let content = conversation::render(&turns, thinking);
+                save_context(&content, &path.to_string_lossy())
+                    .await
+                    .map(|_| remember(&path))
             } else {
                 if let Some(parent) = path.parent().and_then(|e| e.to_str()) {
                     STATE.write().cwd = parent.to_owned();
                 }
                 STATE.write().reload = true;
-                save_html(&content, path.as_path().to_str().unwrap()).await
+                let thinking = if include_thinking {
+                    Thinking::Quoted
+                } else {
+                    Thinking::Omit
+                };
+                let content = conversation::render(&turns, thinking);
+                save_html(&content, &path.to_string_lossy()).await
             }
         } {
             eprintln!("error saving to {:?}", &path);
             eprintln!("{:?}", err);
         }
     }
+    request_repaint();
 }
 
 pub async fn load() {
@@ -61,6 +105,29 @@ pub async fn load() {
     }
 }
 
+pub async fn load_path(path: PathBuf) {
+    if let Err(err) = load_file(path).await {
+        println!("error reading file");
+        println!("{:?}", err);
+    }
+}
+
+/// Loads a file, then runs the commands that must win over what it sets,
+/// such as the model from its header; they are dropped if the load fails
+///
+/// The caller marks the state as retrieving before spawning this.
+pub async fn load_path_then(path: PathBuf, then: Vec<Command>) {
+    match load_file(path).await {
+        Ok(()) => INBOX.write().extend(then),
+        Err(err) => {
+            println!("error reading file");
+            println!("{:?}", err);
+            STATE.write().retrieving = false;
+        }
+    }
+    request_repaint();
+}
+
 async fn do_load() -> Result<()> {
     let cwd = STATE.read().cwd.to_owned();
     if let Some(path) = FileDialog::new()
@@ -69,16 +136,34 @@ async fn do_load() -> Result<()> {
         .add_filter("Context", &["ctx"])
         .pick_file()
     {
-        warn!("opening file: {:?}", &path);
-        if let Some(parent) = path.parent().and_then(|e| e.to_str()) {
-            STATE.write().cwd = parent.to_owned();
-        }
-        let mut parser = Parser(get_content(path.clone())?, Vec::new());
-        parser.load().await?;
+        load_file(path).await?;
+    }
+    Ok(())
+}
+
+async fn load_file(path: PathBuf) -> Result<()> {
+    warn!("opening file: {:?}", &path);
+    if let Some(parent) = path.parent().and_then(|e| e.to_str()) {
+        STATE.write().cwd = parent.to_owned();
     }
+    let mut parser = Parser(get_content(path.clone())?);
+    parser.load().await?;
+    remember(&path);
     Ok(())
 }
 
+/// Keeps track of the current and recently used conversation files
+fn remember(path: &Path) {
+    let Some(path) = path.to_str() else {
+        return;
+    };
+    let mut state = STATE.write();
+    state.path = Some(path.to_owned());
+    state.recent.retain(|e| e != path);
+    state.recent.insert(0, path.to_owned());
+    state.recent.truncate(MAX_RECENT);
+}
+
 fn get_content(path: PathBuf) -> Result<String> {
     use std::io::Read;
 
@@ -95,10 +180,8 @@ impl Drop for Parser {
         state.retrieving = false;
         state.escape = false;
         state.reload = true;
-        if !self.1.is_empty() {
-            // This is the whole point
-            state.context = self.1.clone();
-        }
+        drop(state);
+        request_repaint();
     }
 }
 
@@ -107,21 +190,38 @@ impl Parser {
         warn!("loading context");
         STATE.write().retrieving = true;
         let content = self.0.to_owned();
+        let mut body = String::new();
         let mut step = ReadingHeader;
         for line in content.lines() {
             debug!(step, line);
 
             match step {
                 ReadingHeader => {
+                    if STATE.write().meta.parse_line(line)? {
+                        continue;
+                    }
                     if let Some(model) = line.strip_prefix("model: ") {
                         if !set_model(model) {
                             warn!("using current model");
                         }
-                    } else if let Some(context) = line.strip_prefix("context: ") {
-                        self.1 = context
-                            .split(",")
-                            .map(|e| e.parse::<u32>().unwrap())
-                            .collect::<Vec<_>>();
+                    } else if line.starts_with("context: ") {
+                        // Older files kept the model's own context tokens
+                        warn!("ignoring legacy context");
+                    } else if let Some(strategy) = line.strip_prefix("strategy: ") {
+                        match Strategy::from_key(strategy) {
+                            Some(strategy) => STATE.write().strategy = strategy,
+                            None => {
+                                warn!("unknown strategy {}", strategy);
+                            }
+                        }
+                    } else if let Some(memory) = line.strip_prefix("memory: ") {
+                        STATE.write().memory = serde_json::from_str(memory)?;
+                    } else if let Some(title) = line.strip_prefix("title: ") {
+                        let mut state = STATE.write();
+                        state.title = serde_json::from_str(title)?;
+                        state.title_auto = false;
+                    } else if let Some(system) = line.strip_prefix("system: ") {
+                        STATE.write().system = serde_json::from_str(system)?;
                     } else if line == "-----" {
                         warn!("end of headers");
                         step = ReadingQuestion;
@@ -129,11 +229,12 @@ impl Parser {
                 }
 
                 _ => {
-                    STATE.write().output.push_str(line);
-                    STATE.write().output.push('\n');
+                    body.push_str(line);
+                    body.push('\n');
                 }
             }
         }
+        STATE.write().turns.extend(conversation::parse(&body));
 
         warn!("context loaded");
         Ok(())
@@ -144,13 +245,35 @@ async fn save_html(content: &str, path: &str) -> Result<()> {
     use std::io::Write;
 
     warn!("saving to HTML: {}", path);
+    let (title, model) = {
+        let state = STATE.read();
+        let model = state
+            .models
+            .get(state.selected_model)
+            .cloned()
+            .unwrap_or_default();
+        (state.title.to_owned(), model)
+    };
+    let meta = |name: &str, content: &str| {
+        format!(
+            "    <meta name=\"{}\" content=\"{}\">\n",
+            name,
+            escape_html(content)
+        )
+    };
     let mut file = File::create(path)?;
     file.write_all(b"<!DOCTYPE html>\n")?;
     file.write_all(b"<html>\n")?;
     file.write_all(b"  <head>\n")?;
+    file.write_all(b"    <meta charset=\"utf-8\">\n")?;
     file.write_all(b"    <title>")?;
-    file.write_all(STATE.read().title.as_bytes())?;
+    file.write_all(escape_html(&title).as_bytes())?;
     file.write_all(b"</title>\n")?;
+    file.write_all(meta("generator", &format!("Llama Desktop {}", VERSION)).as_bytes())?;
+    file.write_all(meta("date", &Local::now().to_rfc3339()).as_bytes())?;
+    if !model.is_empty() {
+        file.write_all(meta("model", &model).as_bytes())?;
+    }
     file.write_all(b"  </head>\n")?;
     file.write_all(b"  <body>\n")?;
     file.write_all(markdown_to_html(content, &Options::default()).as_bytes())?;
@@ -169,17 +292,36 @@ pub async fn save_context(content: &str, path: &str) -> Result<()> {
         let state = STATE.read();
         state.models[state.selected_model].to_owned()
     };
-    let context = STATE.read().context.to_owned();
-    if context.is_empty() {
-        return Err(eyre!("no context to save"));
+    let title = STATE.read().title.to_owned();
+    let system = STATE.read().system.to_owned();
+    let strategy = STATE.read().strategy;
+    let memory = STATE.read().memory.to_owned();
+    let meta = STATE.read().meta.header();
+    if content.trim().is_empty() {
+        return Err(eyre!("nothing to save"));
     }
     let mut file = File::create(path)?;
     file.write_all(b"model: ")?;
     file.write_all(model.as_bytes())?;
-    file.write_all(b"\ncontext: ")?;
-    file.write_all(format!("{}", context[0]).as_bytes())?;
-    for part in context.to_owned().iter().skip(1) {
-        file.write_all(format!(",{}", part).as_bytes())?;
+    if !title.is_empty() {
+        file.write_all(b"\ntitle: ")?;
+        file.write_all(serde_json::to_string(&title)?.as_bytes())?;
+    }
+    file.write_all(b"\ndate: ")?;
+    file.write_all(Local::now().to_rfc3339().as_bytes())?;
+    for line in meta {
+        file.write_all(b"\n")?;
+        file.write_all(line.as_bytes())?;
+    }
+    file.write_all(b"\nstrategy: ")?;
+    file.write_all(strategy.key().as_bytes())?;
+    if !memory.is_empty() {
+        file.write_all(b"\nmemory: ")?;
+        file.write_all(serde_json::to_string(&memory)?.as_bytes())?;
+    }
+    if !system.is_empty() {
+        file.write_all(b"\nsystem: ")?;
+        file.write_all(serde_json::to_string(&system)?.as_bytes())?;
     }
     file.write_all(b"\n-----\n")?;
     file.write_all(content.as_bytes())?;
@@ -187,3 +329,10 @@ pub async fn save_context(content: &str, path: &str) -> Result<()> {
 
     Ok(())
 }
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
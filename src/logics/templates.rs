@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fs::File};
+
+use super::{request_repaint, STATE};
+use eyre::Result;
+use rfd::FileDialog;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Template {
+    pub name: String,
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Pack {
+    templates: Vec<Template>,
+}
+
+impl Template {
+    pub fn new(name: impl ToString, body: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            body: body.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Placeholder names in order of first appearance
+    #[must_use]
+    pub fn variables(&self) -> Vec<String> {
+        let mut res: Vec<String> = Vec::new();
+        let mut rest = self.body.as_str();
+        while let Some((_, end, name)) = placeholder(rest) {
+            if !name.is_empty() && !res.iter().any(|e| e == name) {
+                res.push(name.to_owned());
+            }
+            rest = &rest[end..];
+        }
+        res
+    }
+
+    #[must_use]
+    pub fn render(&self, values: &HashMap<String, String>) -> String {
+        let mut res = String::new();
+        let mut rest = self.body.as_str();
+        while let Some((start, end, name)) = placeholder(rest) {
+            res.push_str(&rest[..start]);
+            match values.get(name) {
+                Some(value) => res.push_str(value),
+                None => res.push_str(&rest[start..end]),
+            }
+            rest = &rest[end..];
+        }
+        res.push_str(rest);
+        res
+    }
+}
+
+/// First `{{name}}` in `text` as its start, end and trimmed name; when
+/// braces nest, the innermost pair is the placeholder and the outer `{{`
+/// is left as text
+fn placeholder(text: &str) -> Option<(usize, usize, &str)> {
+    let open = text.find("{{")?;
+    let close = open + 2 + text[open + 2..].find("}}")?;
+    let start = text[..close].rfind("{{")?;
+    Some((start, close + 2, text[start + 2..close].trim()))
+}
+
+#[must_use]
+pub fn default_templates() -> Vec<Template> {
+    vec![
+        Template::new(
+            "Review this diff",
+            "Review the following diff. Point out bugs, risky changes and style issues.\n\n```diff\n{{diff}}\n```",
+        ),
+        Template::new("Summarize", "Summarize the following text:\n\n{{text}}"),
+        Template {
+            temperature: Some(0.25),
+            ..Template::new(
+                "Translate to Portuguese",
+                "Translate the following text to Portuguese:\n\n{{text}}",
+            )
+        },
+    ]
+}
+
+pub fn decode(content: &str) -> Result<Vec<Template>> {
+    Ok(serde_json::from_str(content)?)
+}
+
+pub async fn import_pack() {
+    if let Err(err) = do_import_pack().await {
+        eprintln!("error importing templates");
+        eprintln!("{:?}", err);
+    }
+    request_repaint();
+}
+
+async fn do_import_pack() -> Result<()> {
+    let cwd = STATE.read().cwd.to_owned();
+    if let Some(path) = FileDialog::new()
+        .set_title("Llama Desktop Import Templates")
+        .set_directory(cwd)
+        .add_filter("Template pack", &["json"])
+        .pick_file()
+    {
+        warn!("importing templates from {:?}", &path);
+        let pack: Pack = serde_json::from_reader(File::open(&path)?)?;
+        let mut state = STATE.write();
+        for template in pack.templates {
+            match state.templates.iter_mut().find(|e| e.name == template.name) {
+                Some(current) => *current = template,
+                None => state.templates.push(template),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn export_pack(templates: Vec<Template>) {
+    if let Err(err) = do_export_pack(templates).await {
+        eprintln!("error exporting templates");
+        eprintln!("{:?}", err);
+    }
+}
+
+async fn do_export_pack(templates: Vec<Template>) -> Result<()> {
+    let cwd = STATE.read().cwd.to_owned();
+    if let Some(path) = FileDialog::new()
+        .set_title("Llama Desktop Export Templates")
+        .set_directory(cwd)
+        .add_filter("Template pack", &["json"])
+        .set_file_name("templates.json")
+        .save_file()
+    {
+        warn!("exporting templates to {:?}", &path);
+        serde_json::to_writer_pretty(File::create(&path)?, &Pack { templates })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn variables_in_order_without_repeats() {
+        let template = Template::new("t", "{{ b }} and {{a}}, then {{b}} again {{}}");
+        assert_eq!(template.variables(), vec!["b", "a"]);
+    }
+
+    #[test]
+    fn render_leaves_unknown_placeholders() {
+        let template = Template::new("t", "Translate {{text}} to {{ language }}.");
+        assert_eq!(
+            template.render(&values(&[("text", "olá")])),
+            "Translate olá to {{ language }}."
+        );
+    }
+
+    #[test]
+    fn nested_braces_use_the_innermost_pair() {
+        let template = Template::new("t", "{{outer {{inner}} }} {{{x}}}");
+        assert_eq!(template.variables(), vec!["inner", "x"]);
+        assert_eq!(
+            template.render(&values(&[("inner", "1"), ("x", "2"), ("outer", "3")])),
+            "{{outer 1 }} {2}"
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholder_is_kept_as_text() {
+        let template = Template::new("t", "Hello {{name}}, {{unclosed and {{ more");
+        assert_eq!(template.variables(), vec!["name"]);
+        assert_eq!(
+            template.render(&values(&[("name", "Ana")])),
+            "Hello Ana, {{unclosed and {{ more"
+        );
+        assert!(Template::new("t", "}} {{").variables().is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use super::timeouts::TIMEOUTS;
+use super::{request_repaint, STATE};
+use crate::ollama;
+use crate::protocol::{AdditionalParams, ChatRequest, ChatResponse, Message};
+use eyre::{eyre, Result};
+
+const MAX_TITLE: usize = 60;
+/// Enough of the exchange to tell what it is about
+const MAX_EXCERPT: usize = 2000;
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation \
+below. Answer with the title only, without quotes or final punctuation.";
+
+/// Shortens to `max` characters, cutting at a word boundary when possible
+fn shorten(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut = text.chars().take(max).collect::<String>();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(pos) if pos > max / 2 => &cut[..pos],
+        _ => &cut,
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// Stand-in title taken from the first line of the prompt
+#[must_use]
+pub fn provisional(prompt: &str) -> String {
+    let line = prompt
+        .lines()
+        .map(|line| line.trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    shorten(line, MAX_TITLE)
+}
+
+/// Sets a stand-in title if the conversation has none yet
+pub fn start(prompt: &str) {
+    let mut state = STATE.write();
+    if state.title.is_empty() {
+        state.title = provisional(prompt);
+        state.title_auto = true;
+    }
+}
+
+/// Keeps the first line of the model's answer, without decoration
+fn clean(answer: &str) -> String {
+    let line = answer
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let line = line.strip_prefix("Title:").unwrap_or(line);
+    let line = line
+        .trim_matches(|c: char| c.is_whitespace() || "\"'`*#“”".contains(c))
+        .trim_end_matches(['.', '!', ':', ';']);
+    shorten(line, MAX_TITLE)
+}
+
+/// Asks the model for a short title when the current one is a stand-in
+pub async fn generate() {
+    if let Err(err) = do_generate().await {
+        warn!("error generating title: {:?}", err);
+    }
+}
+
+async fn do_generate() -> Result<()> {
+    let (payload, placeholder) = {
+        let state = STATE.read();
+        if !state.title_auto {
+            return Ok(());
+        }
+        let turn = state.turns.first().ok_or(eyre!("empty conversation"))?;
+        let excerpt = shorten(
+            &format!("User: {}\n\nAssistant: {}", turn.prompt, turn.answer),
+            MAX_EXCERPT,
+        );
+        let model = state
+            .models
+            .get(state.selected_model)
+            .cloned()
+            .ok_or(eyre!("no model selected"))?;
+        let payload = ChatRequest {
+            keep_alive: state.keep_alive(&model),
+            model,
+            messages: vec![
+                Message::new("system", TITLE_PROMPT),
+                Message::new("user", excerpt),
+            ],
+            think: Some(false),
+            stream: true,
+            options: AdditionalParams {
+                num_ctx: 2048,
+                temperature: 0.2,
+                ..Default::default()
+            },
+        };
+        (payload, state.title.to_owned())
+    };
+
+    let timeout = Duration::from_secs(TIMEOUTS[STATE.read().timeout_idx] as u64);
+    let mut answer = String::new();
+    ollama::stream("/api/chat", &payload, timeout, |chunk: ChatResponse| {
+        if let Some(message) = chunk.message {
+            answer.push_str(&message.content);
+        }
+        Ok(())
+    })
+    .await?;
+
+    let title = clean(&answer);
+    if title.is_empty() {
+        return Err(eyre!("the model returned an empty title"));
+    }
+    let mut state = STATE.write();
+    // The user may have renamed it or started over meanwhile
+    if state.title_auto && state.title == placeholder {
+        warn!("title: {}", &title);
+        state.title = title;
+        state.title_auto = false;
+    }
+    drop(state);
+    request_repaint();
+    Ok(())
+}
+
+/// Title turned into a file name, if anything usable is left
+#[must_use]
+pub fn file_stem(title: &str) -> Option<String> {
+    let stem = title
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-");
+    let stem = stem
+        .trim_matches('-')
+        .chars()
+        .take(MAX_TITLE)
+        .collect::<String>();
+    (!stem.is_empty()).then_some(stem)
+}
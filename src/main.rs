@@ -4,6 +4,7 @@ extern crate static_init;
 #[macro_use]
 mod debug;
 
+mod cli;
 mod fonts;
 mod helpers;
 mod logics;
@@ -11,18 +12,58 @@ mod ollama;
 mod protocol;
 mod ui;
 
-use crate::fonts::initialize_fonts;
+use std::env;
+
+use crate::cli::Cli;
+use crate::logics::desktop;
+use crate::logics::ipc::{self, Command};
+use crate::logics::paths::APP_ID;
 use crate::ui::LlamaApp;
 use eframe::egui;
 use eyre::{eyre, Result};
 
 fn main() -> Result<()> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let Cli { commands, endpoint, install, done } = Cli::parse(&args)?;
+    if done {
+        return Ok(());
+    }
+    if install {
+        return desktop::install();
+    }
+    if ipc::forward(&commands)? {
+        if endpoint.is_some() {
+            eprintln!("--endpoint ignored, Llama Desktop is already running");
+        }
+        return Ok(());
+    }
+    if let Some(endpoint) = endpoint {
+        ollama::set_host(&endpoint)?;
+    }
+    // Run the arguments here instead
+    let listener = match ipc::listen() {
+        Ok(Some(listener)) => Some(listener),
+        // Another instance started at the same moment
+        Ok(None) => {
+            if ipc::forward_when_ready(&commands)? {
+                return Ok(());
+            }
+            return Err(eyre!("Llama Desktop is already running but doesn't answer"));
+        }
+        Err(err) => {
+            eprintln!("error opening {:?}: {:?}", ipc::socket_path(), err);
+            None
+        }
+    };
+    ipc::INBOX.write().extend(
+        commands.into_iter().filter(|command| !matches!(command, Command::Focus)),
+    );
+
     let viewport = egui::ViewportBuilder::default()
         .with_title("Llama Desktop")
         .with_inner_size([800.0, 1200.0])
         .with_min_inner_size([800.0, 600.0]);
 
-    let fonts = initialize_fonts().unwrap();
     let options = eframe::NativeOptions {
         viewport,
         centered: true,
@@ -32,10 +73,10 @@ fn main() -> Result<()> {
     };
 
     eframe::run_native(
-        "llama-desktop",
+        APP_ID,
         options,
         Box::new(|cc|
-            Ok(Box::new(LlamaApp::new(cc, fonts)))
+            Ok(Box::new(LlamaApp::new(cc, listener)))
         ),
     ).map_err(|err| eyre!("{:?}", err))?;
 
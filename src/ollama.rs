@@ -1,47 +1,200 @@
 // TODO: move this mod into logics
 
-use crate::protocol::ModelList;
-use std::{env, panic};
+use crate::protocol::{AIModel, LoadedList, LoadedModel, ModelList};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{env, panic, time::Duration};
 use eyre::{eyre, Result};
+use reqwest::header;
+use tokio::time;
 use url::Url;
 
 const DEFAULT_HOST: &str = "http://localhost:11434";
 
 #[dynamic]
-static HOST: Url = get_ollama_host().unwrap();
+static mut HOST: Url = get_ollama_host().unwrap();
+
+/// Points to another Ollama service instead of `OLLAMA_HOST`
+pub fn set_host(uri: &str) -> Result<()> {
+    *HOST.write() = parse_host(uri)?;
+    Ok(())
+}
 
 #[must_use]
 pub fn path(path: &str) -> String {
-    let mut host = HOST.clone();
+    let mut host = Url::clone(&HOST.read());
     host.set_path(path);
     host.to_string()
 }
 
-#[must_use]
-pub async fn get_models() -> Vec<String> {
+/// Installed models, most recently modified first
+pub async fn list_models() -> Result<Vec<AIModel>> {
     let uri = path("/api/tags");
-    let mut models = reqwest::get(uri).await.unwrap()
-        .json::<ModelList>().await.unwrap()
+    let mut models = reqwest::get(uri).await?
+        .error_for_status()?
+        .json::<ModelList>().await?
         .models;
     models.sort_by(|a, b| b.modified_at.cmp(&a.modified_at));
-    let models = models
-        .iter()
-        .map(|model| model.name.to_owned())
-        .collect::<Vec<_>>();
+    Ok(models)
+}
 
+/// Models held in memory right now
+pub async fn list_loaded() -> Result<Vec<LoadedModel>> {
+    let uri = path("/api/ps");
+    let models = reqwest::get(uri).await?
+        .error_for_status()?
+        .json::<LoadedList>().await?
+        .models;
+    Ok(models)
+}
+
+#[must_use]
+pub async fn get_models() -> Vec<AIModel> {
+    let models = list_models().await.unwrap();
     if models.is_empty() {
         panic!("no model found");
     }
     models
 }
 
+/// Posts a streaming request to `endpoint`, handing each chunk to
+/// `on_chunk` until the model is done
+pub async fn stream<T: DeserializeOwned + Done>(
+    endpoint: &str,
+    payload: &impl Serialize,
+    timeout: Duration,
+    mut on_chunk: impl FnMut(T) -> Result<()>,
+) -> Result<()> {
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        "Content-Type",
+        header::HeaderValue::from_static("application/json"),
+    );
+    debug!(&headers);
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()?;
+    let payload = serde_json::to_string(payload)?;
+    let uri = path(endpoint);
+    debug!(&uri);
+
+    let mut response = time::timeout(timeout, client.post(uri).body(payload).send()).await??;
+    if !response.status().is_success() {
+        return Err(eyre!(response.text().await?));
+    }
+    debug!(&response);
+
+    // One chunk may carry several lines, or only part of one
+    let mut pending: Vec<u8> = Vec::new();
+    while let Some(current) = time::timeout(timeout, response.chunk()).await?? {
+        debug!(&current);
+        pending.extend_from_slice(&current);
+        while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+            let line = pending.drain(..=pos).collect::<Vec<_>>();
+            let line = std::str::from_utf8(&line)?.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let chunk: T = serde_json::from_str(line)?;
+            let done = chunk.done();
+            on_chunk(chunk)?;
+            if done {
+                return Ok(());
+            }
+        }
+    }
+    let line = std::str::from_utf8(&pending)?.trim();
+    if !line.is_empty() {
+        on_chunk(serde_json::from_str(line)?)?;
+    }
+    Ok(())
+}
+
+/// Streamed chunks tell when the answer is complete
+pub trait Done {
+    fn done(&self) -> bool;
+}
+
+impl Done for crate::protocol::Response {
+    fn done(&self) -> bool {
+        self.done
+    }
+}
+
+impl Done for crate::protocol::ChatResponse {
+    fn done(&self) -> bool {
+        self.done
+    }
+}
+
 fn get_ollama_host() -> Result<Url> {
     let uri = env::var("OLLAMA_HOST").unwrap_or(DEFAULT_HOST.to_string());
-    let mut uri = Url::parse(&uri)?;
-    if uri.port().is_none() {
-        if let Err(_) = uri.set_port(Some(11434)) {
-            return Err(eyre!("error setting URI port"));
-        };
+    parse_host(&uri)
+}
+
+fn parse_host(uri: &str) -> Result<Url> {
+    let mut uri = Url::parse(uri)?;
+    if uri.port().is_none() && uri.set_port(Some(11434)).is_err() {
+        return Err(eyre!("error setting URI port"));
     }
     Ok(uri)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Response;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves one streamed answer, sent in `parts` cut anywhere in the lines
+    async fn serve(parts: &'static [&'static [u8]]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let _ = socket.read(&mut request).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n")
+                .await
+                .unwrap();
+            for part in parts {
+                socket
+                    .write_all(format!("{:x}\r\n", part.len()).as_bytes())
+                    .await
+                    .unwrap();
+                socket.write_all(part).await.unwrap();
+                socket.write_all(b"\r\n").await.unwrap();
+                socket.flush().await.unwrap();
+                time::sleep(Duration::from_millis(20)).await;
+            }
+            socket.write_all(b"0\r\n\r\n").await.unwrap();
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn lines_split_across_chunks() {
+        // "é" is cut between its two bytes
+        let parts: &[&[u8]] = &[
+            b"{\"response\":\"Hel",
+            b"lo\",\"done\":false}\n{\"response\":\" \xc3",
+            b"\xa9t\xc3\xa9\",\"done\":false}\n\n{\"response\":\"\",",
+            b"\"done\":true,\"eval_count\":2}\n",
+        ];
+        set_host(&serve(parts).await).unwrap();
+
+        let mut chunks = Vec::new();
+        stream("/api/generate", &(), Duration::from_secs(5), |chunk: Response| {
+            chunks.push(chunk);
+            Ok(())
+        })
+        .await
+        .unwrap();
+        let answer: String = chunks.iter().map(|chunk| chunk.response.as_str()).collect();
+        assert_eq!(answer, "Hello été");
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].done);
+        assert_eq!(chunks[2].eval_count, Some(2));
+    }
+}
@@ -2,10 +2,27 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct AIModel {
     pub name: String,
     pub modified_at: String,
+    /// Bytes on disk
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub details: ModelDetails,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ModelDetails {
+    #[serde(default)]
+    pub family: String,
+    /// Such as `8.0B`
+    #[serde(default)]
+    pub parameter_size: String,
+    /// Such as `Q4_K_M`
+    #[serde(default)]
+    pub quantization_level: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -13,6 +30,23 @@ pub struct ModelList {
     pub models: Vec<AIModel>,
 }
 
+/// A model Ollama holds in memory, as listed by `/api/ps`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LoadedModel {
+    pub name: String,
+    /// Bytes held in GPU memory
+    #[serde(default)]
+    pub size_vram: u64,
+    /// When Ollama unloads it, far in the future when kept forever
+    #[serde(default)]
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadedList {
+    pub models: Vec<LoadedModel>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct AdditionalParams {
     pub num_ctx: usize,
@@ -25,16 +59,74 @@ pub struct AdditionalParams {
 pub struct Request {
     pub model: String,
     pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think: Option<bool>,
+    /// How long the model stays loaded afterwards, `None` leaves it to Ollama
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
     pub stream: bool,
-    pub options: AdditionalParams,
-    pub context: Option<Vec<u32>>,
+    /// `None` keeps the settings of an already loaded model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<AdditionalParams>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Response {
     pub response: String,
-    pub context: Option<Vec<u32>>,
+    #[serde(default)]
+    pub thinking: Option<String>,
     pub done: bool,
+    /// Statistics, only present in the last chunk
+    #[serde(default)]
+    pub prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+    /// Nanoseconds spent generating the answer
+    #[serde(default)]
+    pub eval_duration: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think: Option<bool>,
+    /// How long the model stays loaded afterwards, `None` leaves it to Ollama
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    pub stream: bool,
+    pub options: AdditionalParams,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatResponse {
+    #[serde(default)]
+    pub message: Option<Message>,
+    pub done: bool,
+    #[serde(default)]
+    pub eval_count: Option<u64>,
+}
+
+impl Message {
+    #[must_use]
+    pub fn new(role: &str, content: impl ToString) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            thinking: None,
+        }
+    }
 }
 
 impl Default for AdditionalParams {
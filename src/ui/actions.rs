@@ -0,0 +1,76 @@
+use super::keymap::Action;
+use super::{BoxLayout, LlamaApp, RUNTIME};
+use crate::logics::compare::COMPARISON;
+use crate::logics::{storage, Sender, STATE};
+use eframe::egui::{Button, Context, RichText, Ui, ViewportCommand, Widget};
+
+impl LlamaApp {
+    pub(super) fn run_action(&mut self, ctx: &Context, action: Action) {
+        let retrieving = STATE.read().retrieving;
+        debug!(action, retrieving);
+        match action {
+            Action::New if !retrieving => STATE.write().reset(),
+            Action::Load if !retrieving => {
+                RUNTIME.spawn(storage::load());
+            }
+            Action::Save if !retrieving && !STATE.read().turns.is_empty() => {
+                RUNTIME.spawn(storage::save_content(STATE.read().turns.clone()));
+            }
+            // During a comparison the prompt waits in the queue
+            Action::Send if !retrieving && !COMPARISON.read().running => {
+                RUNTIME.spawn(Sender::new(self.temperature).send());
+            }
+            Action::Send => self.enqueue_input(),
+            Action::Stop if retrieving => STATE.write().escape = true,
+            Action::Stop if COMPARISON.read().running => COMPARISON.write().cancel = true,
+            Action::Quit => ctx.send_viewport_cmd(ViewportCommand::Close),
+            Action::SystemPrompt => self.system_open = true,
+            Action::Context => self.context_open = true,
+            Action::InsertTemplate => self.toggle_template_palette(),
+            Action::ManageTemplates => self.open_template_manager(),
+            Action::CommandPalette => self.toggle_palette(),
+            Action::Compare => self.open_compare(),
+            Action::Preload => self.preload_model(),
+            Action::Library => self.open_library(),
+            Action::Find => self.open_find(),
+            Action::HistorySearch => self.search_history(),
+            Action::Shortcuts => self.open_shortcuts(),
+            Action::Theme => self.open_theme_editor(),
+            Action::Fonts => self.open_font_settings(),
+            Action::Preferences => self.open_preferences(),
+            Action::ZoomIn => self.zoom(ctx, Some(1.0)),
+            Action::ZoomOut => self.zoom(ctx, Some(-1.0)),
+            Action::ZoomReset => self.zoom(ctx, None),
+            Action::CompactInput => self.toggle_compact_input(),
+            Action::FocusMode => self.toggle_focus_mode(),
+            Action::LayoutVertical => self.set_layout(BoxLayout::Vertically),
+            Action::LayoutHorizontal => self.set_layout(BoxLayout::Horizontally),
+            _ => {
+                warn!("action {:?} not available now", action);
+            }
+        }
+    }
+
+    /// Menu entry showing the action's current key chord
+    pub(super) fn menu_item(
+        &self,
+        ui: &mut Ui,
+        text: &str,
+        action: Action,
+        actions: &mut Vec<Action>,
+    ) {
+        if Button::new(RichText::new(text).strong())
+            .shortcut_text(self.keymap.label(ui.ctx(), action))
+            .ui(ui)
+            .clicked()
+        {
+            actions.push(action);
+        }
+    }
+
+    pub(super) fn set_layout(&mut self, layout: BoxLayout) {
+        if layout != BoxLayout::NotSet {
+            self.box_layout = layout;
+        }
+    }
+}
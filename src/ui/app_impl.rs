@@ -1,8 +1,19 @@
+use std::os::unix::net::UnixListener;
 use std::{thread, time::Duration};
 
+use super::font_settings::FontsUi;
+use super::keymap::Keymap;
+use super::library::LibraryUi;
+use super::output::OutputLayout;
+use super::panes::Panes;
+use super::recovery::Journal;
+use super::settings::Settings;
+use super::theme::Themes;
 use super::{BoxLayout, LlamaApp, RUNTIME};
-use crate::logics::{STATE, TIMEOUTS};
+use crate::logics::{history, ipc, models};
+use crate::logics::{set_waker, STATE};
 use crate::ollama;
+use crate::protocol::AIModel;
 use eframe::Frame;
 use eframe::*;
 use egui::*;
@@ -11,15 +22,18 @@ use tokio::sync::oneshot;
 
 /// LlamaApp is just a proxy for a module
 impl LlamaApp {
-    pub fn new(cc: &CreationContext<'_>, fonts: FontDefinitions) -> Self {
+    pub fn new(cc: &CreationContext<'_>, listener: Option<UnixListener>) -> Self {
         install_image_loaders(&cc.egui_ctx);
-        cc.egui_ctx.set_fonts(fonts);
+        let ctx = cc.egui_ctx.clone();
+        set_waker(move || ctx.request_repaint());
+        cc.egui_ctx
+            .options_mut(|opts| opts.zoom_with_keyboard = false);
 
-        let (inp, mut out) = oneshot::channel::<Vec<String>>();
+        let (inp, mut out) = oneshot::channel::<Vec<AIModel>>();
         RUNTIME.spawn(async move {
             inp.send(ollama::get_models().await).unwrap();
         });
-        STATE.write().models = loop {
+        models::set_catalog(loop {
             match out.try_recv() {
                 Ok(cache) => break cache,
                 Err(oneshot::error::TryRecvError::Empty) => {
@@ -30,9 +44,9 @@ impl LlamaApp {
                     panic!("failed retrieving models");
                 }
             }
-        };
+        });
 
-        Self {
+        let mut app = Self {
             logo: include_image!("../assets/logo.png"),
             horizontal: include_image!("../assets/horizontal.png"),
             vertical: include_image!("../assets/vertical.png"),
@@ -40,9 +54,37 @@ impl LlamaApp {
             small_font: FontId::new(12.0, FontFamily::Name("arial".into())),
             box_layout: BoxLayout::default(),
             temperature: 0.75,
-            last_temperature: 0.75,
             setupdone: false,
+            system_open: false,
+            context_open: false,
+            library: LibraryUi::default(),
+            templates: Default::default(),
+            keymap: Keymap::default(),
+            palette: Default::default(),
+            shortcuts: Default::default(),
+            themes: Themes::load(),
+            panes: Panes::default(),
+            output: Default::default(),
+            find: Default::default(),
+            history: Default::default(),
+            journal: Journal::start(),
+            window_title: String::new(),
+            compare: Default::default(),
+            fonts: FontsUi::default(),
+            model_picker: Default::default(),
+            preferences: Default::default(),
+            stored: Default::default(),
+        };
+        let settings = cc.storage.map(Settings::load).unwrap_or_default();
+        // Fonts are loaded below either way
+        app.fonts.settings = settings.appearance.fonts.clone();
+        app.apply_settings(&cc.egui_ctx, settings);
+        app.apply_fonts(&cc.egui_ctx);
+        RUNTIME.spawn(models::watch());
+        if let Some(listener) = listener {
+            RUNTIME.spawn(ipc::serve(listener));
         }
+        app
     }
 
     pub(super) fn setup(&mut self, frame: &mut Frame) {
@@ -51,69 +93,14 @@ impl LlamaApp {
         }
         warn!("running setup");
         if let Some(storage) = frame.storage() {
-            self.setup_model(storage);
-            self.setup_timeout(storage);
-            self.setup_layout(storage);
-            self.setup_cwd(storage);
-            self.setup_temperature(storage);
-        } else {
-            let mut state = STATE.write();
-            state.selected_model = 0;
-            state.timeout_idx = 1;
-            state.cwd = env!["HOME"].to_string();
-            self.box_layout = BoxLayout::Vertically;
+            STATE.write().recent = storage
+                .get_string("recent")
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .unwrap_or_default();
+            self.output = OutputLayout::load(storage.get_string("scroll-positions"));
         }
+        STATE.write().history = history::load();
         self.setupdone = true;
         debug!(self);
     }
-
-    fn setup_model(&mut self, storage: &dyn Storage) {
-        let selected_model = storage
-            .get_string("selected-model")
-            .unwrap_or("0".to_string());
-        let idx: usize = selected_model.parse().unwrap_or(0);
-        if idx < STATE.read().models.len() {
-            STATE.write().selected_model = idx;
-        } else {
-            STATE.write().selected_model = 0;
-        }
-    }
-
-    fn setup_timeout(&mut self, storage: &dyn Storage) {
-        let timeout: usize = storage
-            .get_string("timeout")
-            .unwrap_or("20".to_string())
-            .parse()
-            .unwrap_or(20);
-        for (idx, tm) in TIMEOUTS.iter().enumerate() {
-            if *tm == timeout {
-                STATE.write().timeout_idx = idx;
-                return;
-            }
-        }
-        STATE.write().timeout_idx = 1;
-    }
-
-    fn setup_layout(&mut self, storage: &dyn Storage) {
-        if storage.get_string("layout").unwrap_or("V".to_string()) == *"H" {
-            self.box_layout = BoxLayout::Horizontally;
-        } else {
-            self.box_layout = BoxLayout::Vertically;
-        }
-    }
-
-    fn setup_cwd(&mut self, storage: &dyn Storage) {
-        STATE.write().cwd = storage
-            .get_string("cwd")
-            .unwrap_or(env!["HOME"].to_string());
-    }
-
-    fn setup_temperature(&mut self, storage: &dyn Storage) {
-        self.temperature = storage
-            .get_string("temperature")
-            .unwrap_or("0.75".to_string())
-            .parse()
-            .unwrap_or(0.75);
-        self.last_temperature = self.temperature;
-    }
 }
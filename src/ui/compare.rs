@@ -0,0 +1,178 @@
+use super::{LlamaApp, MD_CACHE, RUNTIME};
+use crate::logics::compare::{self, Candidate, COMPARISON};
+use crate::logics::titles;
+use crate::logics::STATE;
+use eframe::egui::*;
+use egui_commonmark::CommonMarkViewer;
+
+#[derive(Debug, Default)]
+pub(super) struct CompareUi {
+    open: bool,
+    models: Vec<String>,
+    parallel: bool,
+}
+
+impl LlamaApp {
+    pub(super) fn open_compare(&mut self) {
+        self.compare.open = true;
+        if self.compare.models.is_empty() {
+            let state = STATE.read();
+            if let Some(model) = state.models.get(state.selected_model) {
+                self.compare.models.push(model.to_owned());
+            }
+        }
+    }
+
+    pub(super) fn show_compare(&mut self, ctx: &Context) {
+        if !self.compare.open {
+            return;
+        }
+        let (syntax_dark, syntax_light) = self.themes.syntax();
+        let models = STATE.read().models.clone();
+        let retrieving = STATE.read().retrieving;
+        let prompt = STATE.read().input.trim().to_string();
+        let running = COMPARISON.read().running;
+        let mut adopt: Option<usize> = None;
+        let mut open = true;
+
+        Window::new("Compare Models")
+            .open(&mut open)
+            .default_size(Vec2::new(960.0, 640.0))
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(RichText::new("Models:").strong());
+                    for model in models.iter() {
+                        let mut checked = self.compare.models.contains(model);
+                        if ui.checkbox(&mut checked, model).changed() {
+                            if checked {
+                                self.compare.models.push(model.to_owned());
+                            } else {
+                                self.compare.models.retain(|e| e != model);
+                            }
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.compare.parallel, false, "One after another");
+                    ui.radio_value(&mut self.compare.parallel, true, "In parallel");
+                    ui.separator();
+                    if running {
+                        if ui.button("Cancel").clicked() {
+                            COMPARISON.write().cancel = true;
+                        }
+                        ui.spinner();
+                    } else {
+                        let ready =
+                            self.compare.models.len() >= 2 && !prompt.is_empty() && !retrieving;
+                        if ui
+                            .add_enabled(ready, Button::new("Compare"))
+                            .on_hover_text("Send the input to every selected model")
+                            .on_disabled_hover_text(
+                                "Write a prompt, select at least two models and wait for the answer",
+                            )
+                            .clicked()
+                        {
+                            let run = compare::begin(&prompt, &self.compare.models);
+                            RUNTIME.spawn(compare::run(
+                                run,
+                                self.temperature,
+                                self.compare.parallel,
+                            ));
+                        }
+                    }
+                });
+                ui.label(
+                    RichText::new(
+                        "Each model answers on its own, without the conversation so far.",
+                    )
+                    .weak(),
+                );
+                ui.separator();
+
+                let comparison = COMPARISON.read();
+                if comparison.candidates.is_empty() {
+                    return;
+                }
+                ui.label(RichText::new(format!("> {}", comparison.prompt)).italics());
+                let count = comparison.candidates.len();
+                ui.columns(count, |cols| {
+                    for (idx, candidate) in comparison.candidates.iter().enumerate() {
+                        let ui = &mut cols[idx];
+                        let preferred = comparison.preferred == Some(idx);
+                        ui.horizontal(|ui| {
+                            let title = RichText::new(&candidate.model).strong();
+                            if preferred {
+                                ui.label(title.color(self.themes.accent()));
+                                ui.label("★");
+                            } else {
+                                ui.label(title);
+                            }
+                        });
+                        ui.label(RichText::new(stats(candidate)).small().weak());
+                        let usable = candidate.is_done()
+                            && candidate.error.is_none()
+                            && !running
+                            && !retrieving;
+                        if ui
+                            .add_enabled(usable, Button::new("Prefer and continue"))
+                            .on_hover_text(
+                                "Add this answer to the conversation and switch to its model",
+                            )
+                            .clicked()
+                        {
+                            adopt = Some(idx);
+                        }
+                        ui.separator();
+                        ScrollArea::vertical()
+                            .id_salt(("compare", idx))
+                            .auto_shrink([false; 2])
+                            .show(ui, |ui| {
+                                if let Some(err) = candidate.error.as_ref() {
+                                    ui.colored_label(ui.visuals().error_fg_color, err);
+                                }
+                                CommonMarkViewer::default()
+                                    .syntax_theme_dark(syntax_dark)
+                                    .syntax_theme_light(syntax_light)
+                                    .show(ui, &mut MD_CACHE.write(), &candidate.turn.answer);
+                            });
+                    }
+                });
+            });
+        self.compare.open = open;
+
+        if let Some(idx) = adopt {
+            match compare::adopt(idx) {
+                Ok(()) => {
+                    self.compare.open = false;
+                    RUNTIME.spawn(titles::generate());
+                }
+                Err(err) => eprintln!("error adopting answer: {:?}", err),
+            }
+        }
+    }
+}
+
+/// Timing and token statistics of an answer
+fn stats(candidate: &Candidate) -> String {
+    if candidate.started.is_none() {
+        return "waiting".to_string();
+    }
+    let mut parts = Vec::new();
+    if let Some(first) = candidate.first_token {
+        parts.push(format!("first token {:.1}s", first.as_secs_f32()));
+    }
+    match candidate.elapsed {
+        Some(elapsed) => parts.push(format!("total {:.1}s", elapsed.as_secs_f32())),
+        None => parts.push("generating…".to_string()),
+    }
+    if let Some(tokens) = candidate.tokens {
+        parts.push(format!("{} tokens", tokens));
+    }
+    if let Some(prompt) = candidate.prompt_tokens {
+        parts.push(format!("{} prompt tokens", prompt));
+    }
+    if let Some(rate) = candidate.tokens_per_second {
+        parts.push(format!("{:.1} tok/s", rate));
+    }
+    parts.join(" · ")
+}
@@ -0,0 +1,123 @@
+use super::{LlamaApp, RUNTIME};
+use crate::logics::context::{self, Strategy, CONTEXT_SIZES};
+use crate::logics::STATE;
+use eframe::egui::*;
+
+/// Share of the budget from which the meter turns to warning colours
+const WARN_AT: f32 = 0.8;
+
+pub(super) fn size_label(size: usize) -> String {
+    format!("{}k", size / 1024)
+}
+
+/// Filled part of the budget, with a colour telling how close it is to full
+fn meter(ui: &mut Ui, width: f32) -> Response {
+    let (used, budget) = context::usage(&STATE.read());
+    let fill = used as f32 / budget.max(1) as f32;
+    let color = if fill > 1.0 {
+        ui.visuals().error_fg_color
+    } else if fill > WARN_AT {
+        ui.visuals().warn_fg_color
+    } else {
+        ui.visuals().selection.bg_fill
+    };
+    ui.add(
+        ProgressBar::new(fill.min(1.0))
+            .desired_width(width)
+            .fill(color)
+            .text(format!("~{} / {} tokens", used, budget)),
+    )
+}
+
+impl LlamaApp {
+    /// Budget meter laid over the bottom left corner of the conversation
+    pub(super) fn show_context_meter(&mut self, ui: &mut Ui, rect: Rect) {
+        let mut child = ui.new_child(
+            UiBuilder::new()
+                .max_rect(rect.shrink(8.0))
+                .layout(Layout::bottom_up(Align::Min)),
+        );
+        let response = meter(&mut child, 180.0)
+            .interact(Sense::click())
+            .on_hover_text("Estimated context used by the next prompt. Click for settings");
+        if response.clicked() {
+            self.context_open = true;
+        }
+    }
+
+    pub(super) fn show_context(&mut self, ctx: &Context) {
+        if !self.context_open {
+            return;
+        }
+        let retrieving = STATE.read().retrieving;
+        let mut open = true;
+        Window::new("Context Window")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                meter(ui, ui.available_width());
+                ui.add_space(4.0);
+
+                ui.horizontal(|ui| {
+                    let mut num_ctx = STATE.read().num_ctx;
+                    ComboBox::from_label("tokens")
+                        .selected_text(size_label(num_ctx))
+                        .show_ui(ui, |ui| {
+                            for size in CONTEXT_SIZES {
+                                ui.selectable_value(&mut num_ctx, size, size_label(size));
+                            }
+                        });
+                    if num_ctx != STATE.read().num_ctx {
+                        STATE.write().num_ctx = num_ctx;
+                    }
+                })
+                .response
+                .on_hover_text("Larger windows need more memory and a model trained for them");
+
+                ui.separator();
+                ui.label(RichText::new("When the conversation no longer fits:").strong());
+                {
+                    let mut state = STATE.write();
+                    for strategy in Strategy::ALL {
+                        ui.radio_value(&mut state.strategy, strategy, strategy.label());
+                    }
+                }
+                ui.label(
+                    RichText::new("Saved with the conversation. Pinned turns are always kept.")
+                        .weak(),
+                );
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Memory").strong());
+                    let has_turns = !STATE.read().turns.is_empty();
+                    if ui
+                        .add_enabled(!retrieving && has_turns, Button::new("Summarize now"))
+                        .on_hover_text("Fold the older unpinned turns into the memory")
+                        .clicked()
+                    {
+                        RUNTIME.spawn(context::summarize_now(self.temperature));
+                    }
+                    let has_memory = !STATE.read().memory.is_empty();
+                    if ui
+                        .add_enabled(!retrieving && has_memory, Button::new("Forget"))
+                        .on_hover_text("Drop the memory and send the summarized turns again")
+                        .clicked()
+                    {
+                        context::forget();
+                    }
+                });
+                ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    ui.add_enabled(
+                        !retrieving,
+                        TextEdit::multiline(&mut STATE.write().memory)
+                            .hint_text(
+                                "Summary of the earlier conversation, sent with every prompt",
+                            )
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            });
+        self.context_open = open;
+    }
+}
@@ -0,0 +1,755 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+use super::LlamaApp;
+use crate::logics::conversation::Turn;
+use eframe::egui::text::LayoutJob;
+use eframe::egui::*;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use regex::{Regex, RegexBuilder};
+
+const QUERY_ID: &str = "find-query";
+
+/// Which side of the conversation a search looks into
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(super) enum FindScope {
+    #[default]
+    All,
+    Prompts,
+    Answers,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Match {
+    pub(super) turn: usize,
+    pub(super) prompt: bool,
+    pub(super) range: Range<usize>,
+}
+
+/// Matches found in one turn, and a digest of the text they were found in
+#[derive(Debug, Default)]
+struct TurnMatches {
+    digest: u64,
+    prompt: Vec<Range<usize>>,
+    answer: Vec<Range<usize>>,
+}
+
+impl TurnMatches {
+    #[must_use]
+    fn digest(turn: &Turn) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        turn.prompt.hash(&mut hasher);
+        turn.answer.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct FindBar {
+    pub(super) open: bool,
+    query: String,
+    case_sensitive: bool,
+    regex: bool,
+    scope: FindScope,
+    matches: Vec<Match>,
+    current: usize,
+    /// Scroll to the current match on the next frame
+    pub(super) scroll: bool,
+    error: Option<String>,
+    focus: bool,
+    searched: Option<(String, bool, bool, FindScope)>,
+    pattern: Option<Regex>,
+    /// Searched again only when the query or the turn changes
+    cache: Vec<TurnMatches>,
+}
+
+impl FindBar {
+    fn compile(&self) -> Result<Option<Regex>, regex::Error> {
+        if self.query.is_empty() {
+            return Ok(None);
+        }
+        let pattern = if self.regex {
+            self.query.to_owned()
+        } else {
+            regex::escape(&self.query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map(Some)
+    }
+
+    /// Searches the turns that changed, keeping the current match if possible
+    pub(super) fn update(&mut self, turns: &[Turn]) {
+        if !self.open {
+            self.matches.clear();
+            self.cache.clear();
+            return;
+        }
+        let searched = Some((
+            self.query.to_owned(),
+            self.case_sensitive,
+            self.regex,
+            self.scope,
+        ));
+        let changed = searched != self.searched;
+        if changed {
+            self.pattern = match self.compile() {
+                Ok(pattern) => {
+                    self.error = None;
+                    pattern
+                }
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    None
+                }
+            };
+            self.cache.clear();
+        }
+
+        let mut dirty = changed || self.cache.len() != turns.len();
+        self.cache.truncate(turns.len());
+        for (idx, turn) in turns.iter().enumerate() {
+            let digest = TurnMatches::digest(turn);
+            if self
+                .cache
+                .get(idx)
+                .is_some_and(|cached| cached.digest == digest)
+            {
+                continue;
+            }
+            let search = |text: &str| match self.pattern.as_ref() {
+                Some(pattern) => pattern
+                    .find_iter(text)
+                    .filter(|found| !found.is_empty())
+                    .map(|found| found.range())
+                    .collect(),
+                None => Vec::new(),
+            };
+            let found = TurnMatches {
+                digest,
+                prompt: search(&turn.prompt),
+                answer: search(&turn.answer),
+            };
+            match self.cache.get_mut(idx) {
+                Some(cached) => *cached = found,
+                None => self.cache.push(found),
+            }
+            dirty = true;
+        }
+
+        if dirty {
+            let mut matches = Vec::new();
+            for (turn, cached) in self.cache.iter().enumerate() {
+                for (prompt, ranges) in [(true, &cached.prompt), (false, &cached.answer)] {
+                    let skip = match self.scope {
+                        FindScope::All => false,
+                        FindScope::Prompts => !prompt,
+                        FindScope::Answers => prompt,
+                    };
+                    if skip {
+                        continue;
+                    }
+                    matches.extend(ranges.iter().map(|range| Match {
+                        turn,
+                        prompt,
+                        range: range.clone(),
+                    }));
+                }
+            }
+            self.matches = matches;
+        }
+        if changed {
+            self.searched = searched;
+            self.current = 0;
+            self.scroll = !self.matches.is_empty();
+        }
+        if self.current >= self.matches.len() {
+            self.current = 0;
+        }
+    }
+
+    fn step(&mut self, forward: bool) {
+        let count = self.matches.len();
+        if count == 0 {
+            return;
+        }
+        self.current = if forward {
+            (self.current + 1) % count
+        } else {
+            (self.current + count - 1) % count
+        };
+        self.scroll = true;
+    }
+
+    #[must_use]
+    pub(super) fn current(&self) -> Option<&Match> {
+        self.matches.get(self.current)
+    }
+
+    /// Matches inside one side of a turn, flagging the current one
+    #[must_use]
+    pub(super) fn hits(&self, turn: usize, prompt: bool) -> Vec<(Range<usize>, bool)> {
+        self.matches
+            .iter()
+            .enumerate()
+            .filter(|(_, found)| found.turn == turn && found.prompt == prompt)
+            .map(|(idx, found)| (found.range.clone(), idx == self.current))
+            .collect()
+    }
+
+    /// Turn to lay out even when off screen, so it can be scrolled to
+    #[must_use]
+    pub(super) fn target(&self) -> Option<usize> {
+        self.scroll
+            .then(|| self.current().map(|found| found.turn))
+            .flatten()
+    }
+
+    /// Bar laid over the top right corner of the conversation
+    pub(super) fn show(&mut self, ui: &mut Ui, rect: Rect) {
+        let mut child = ui.new_child(
+            UiBuilder::new()
+                .max_rect(rect.shrink(8.0))
+                .layout(Layout::right_to_left(Align::Min)),
+        );
+        Frame::popup(child.style()).show(&mut child, |ui| {
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    TextEdit::singleline(&mut self.query)
+                        .id(Id::new(QUERY_ID))
+                        .hint_text("Find")
+                        .desired_width(200.0),
+                );
+                if self.focus {
+                    self.focus = false;
+                    response.request_focus();
+                }
+                if response.lost_focus() {
+                    let (enter, shift, escape) = ui.input_mut(|rd| {
+                        (
+                            rd.key_pressed(Key::Enter),
+                            rd.modifiers.shift,
+                            rd.consume_key(Modifiers::NONE, Key::Escape),
+                        )
+                    });
+                    if enter {
+                        self.step(!shift);
+                        response.request_focus();
+                    } else if escape {
+                        self.open = false;
+                    }
+                }
+                match (&self.error, self.matches.len()) {
+                    (Some(err), _) => {
+                        ui.colored_label(ui.visuals().error_fg_color, "Invalid pattern")
+                            .on_hover_text(err);
+                    }
+                    (None, 0) if !self.query.is_empty() => {
+                        ui.colored_label(ui.visuals().warn_fg_color, "No matches");
+                    }
+                    (None, 0) => (),
+                    (None, count) => {
+                        ui.label(format!("{} of {}", self.current + 1, count));
+                    }
+                }
+                if ui
+                    .small_button("⏶")
+                    .on_hover_text("Previous match")
+                    .clicked()
+                {
+                    self.step(false);
+                }
+                if ui.small_button("⏷").on_hover_text("Next match").clicked() {
+                    self.step(true);
+                }
+                ui.toggle_value(&mut self.case_sensitive, "Aa")
+                    .on_hover_text("Case sensitive");
+                ui.toggle_value(&mut self.regex, ".*")
+                    .on_hover_text("Regular expression");
+                ComboBox::from_id_salt("find-scope")
+                    .width(96.0)
+                    .selected_text(match self.scope {
+                        FindScope::All => "Everywhere",
+                        FindScope::Prompts => "Prompts",
+                        FindScope::Answers => "Answers",
+                    })
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.scope, FindScope::All, "Everywhere");
+                        ui.selectable_value(&mut self.scope, FindScope::Prompts, "Prompts");
+                        ui.selectable_value(&mut self.scope, FindScope::Answers, "Answers");
+                    });
+                if ui.small_button("✖").on_hover_text("Close").clicked() {
+                    self.open = false;
+                }
+            });
+        });
+    }
+}
+
+impl LlamaApp {
+    pub(super) fn open_find(&mut self) {
+        self.find.open = true;
+        self.find.focus = true;
+    }
+}
+
+/// Matches falling inside a slice that starts at `offset`, made relative to it
+#[must_use]
+pub(super) fn local_hits(
+    hits: &[(Range<usize>, bool)],
+    offset: usize,
+    len: usize,
+) -> Vec<(Range<usize>, bool)> {
+    hits.iter()
+        .filter(|(range, _)| range.start >= offset && range.start < offset + len)
+        .map(|(range, current)| {
+            (
+                range.start - offset..range.end.min(offset + len) - offset,
+                *current,
+            )
+        })
+        .collect()
+}
+
+/// Lays out plain text with the given matches highlighted, returning where
+/// the current match ended up
+pub(super) fn show_highlighted(
+    ui: &mut Ui,
+    text: &str,
+    hits: &[(Range<usize>, bool)],
+    font: FontId,
+) -> Option<Rect> {
+    let color = ui.visuals().text_color();
+    let plain = TextFormat::simple(font.clone(), color);
+    let highlight = |current: bool| TextFormat {
+        background: if current {
+            ui.visuals().warn_fg_color.gamma_multiply(0.6)
+        } else {
+            ui.visuals().selection.bg_fill
+        },
+        ..TextFormat::simple(font.clone(), color)
+    };
+
+    let mut job = LayoutJob::default();
+    let mut pos = 0;
+    let mut current_char: Option<usize> = None;
+    for (range, current) in hits.iter() {
+        let (start, end) = (range.start.max(pos), range.end.min(text.len()));
+        if start >= end {
+            continue;
+        }
+        job.append(&text[pos..start], 0.0, plain.clone());
+        if *current {
+            current_char = Some(text[..start].chars().count());
+        }
+        job.append(&text[start..end], 0.0, highlight(*current));
+        pos = end;
+    }
+    job.append(&text[pos..], 0.0, plain);
+    job.wrap.max_width = ui.available_width();
+
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let response = ui.label(galley.clone());
+    current_char.map(|idx| {
+        galley
+            .pos_from_ccursor(text::CCursor::new(idx))
+            .translate(response.rect.min.to_vec2())
+    })
+}
+
+/// Lays out Markdown with the given matches highlighted, keeping headings,
+/// lists, emphasis and code styled, and returns where the current match ended up
+pub(super) fn show_highlighted_markdown(
+    ui: &mut Ui,
+    text: &str,
+    hits: &[(Range<usize>, bool)],
+    quote: bool,
+) -> Option<Rect> {
+    let layout = markdown_job(ui, text, hits, quote);
+    let (mut job, current_char) = (layout.job, layout.current);
+    job.wrap.max_width = ui.available_width();
+    let galley = ui.fonts(|fonts| fonts.layout_job(job));
+    let response = ui.label(galley.clone());
+    current_char.map(|idx| {
+        galley
+            .pos_from_ccursor(text::CCursor::new(idx))
+            .translate(response.rect.min.to_vec2())
+    })
+}
+
+#[must_use]
+pub(super) fn markdown_job<'a>(
+    ui: &Ui,
+    text: &str,
+    hits: &'a [(Range<usize>, bool)],
+    quote: bool,
+) -> MarkdownJob<'a> {
+    let mut layout = MarkdownJob::new(ui, hits);
+    layout.quote = usize::from(quote);
+    let parser = Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES);
+    for (event, range) in parser.into_offset_iter() {
+        // Where the event's text sits in the source, when it is taken as is
+        let exact =
+            |content: &str| (text.get(range.clone()) == Some(content)).then_some(range.start);
+        match event {
+            Event::Start(tag) => layout.start(tag),
+            Event::End(tag) => layout.end(tag),
+            Event::Text(content) => layout.text(&content, exact(&content), false),
+            Event::Code(content) => {
+                let source = text
+                    .get(range.clone())
+                    .and_then(|raw| raw.find(&*content))
+                    .map(|idx| range.start + idx);
+                layout.text(&content, source, true);
+            }
+            Event::Html(content) | Event::InlineHtml(content) => {
+                layout.text(&content, exact(&content), false)
+            }
+            Event::InlineMath(content) | Event::DisplayMath(content) => {
+                layout.text(&content, None, true)
+            }
+            Event::FootnoteReference(name) => layout.text(&format!("[{}]", name), None, false),
+            Event::SoftBreak => layout.text(" ", None, false),
+            Event::HardBreak => layout.text("\n", None, false),
+            Event::Rule => {
+                layout.block_break();
+                layout.text("———", None, false);
+                layout.line_break();
+            }
+            Event::TaskListMarker(done) => layout.text(if done { "☑ " } else { "☐ " }, None, false),
+        }
+    }
+    layout
+}
+
+/// Builds one layout job out of Markdown events, styled close to the viewer
+pub(super) struct MarkdownJob<'a> {
+    visuals: Visuals,
+    body: FontId,
+    monospace: FontId,
+    heading_size: f32,
+    hits: &'a [(Range<usize>, bool)],
+    pub(super) job: LayoutJob,
+    /// Character where the current match starts
+    current: Option<usize>,
+    /// Character ranges of the links and where they point to
+    pub(super) links: Vec<(Range<usize>, String)>,
+    heading: Option<HeadingLevel>,
+    strong: bool,
+    emphasis: bool,
+    strikethrough: bool,
+    /// First character and target of the link being laid out
+    link: Option<(usize, String)>,
+    code_block: bool,
+    quote: usize,
+    /// Next number of each open list, `None` for bullets
+    lists: Vec<Option<u64>>,
+    /// Right after a list marker, where a paragraph must not break the line
+    item: bool,
+}
+
+impl<'a> MarkdownJob<'a> {
+    fn new(ui: &Ui, hits: &'a [(Range<usize>, bool)]) -> Self {
+        Self {
+            visuals: ui.visuals().clone(),
+            body: TextStyle::Body.resolve(ui.style()),
+            monospace: TextStyle::Monospace.resolve(ui.style()),
+            heading_size: TextStyle::Heading.resolve(ui.style()).size,
+            hits,
+            job: LayoutJob::default(),
+            current: None,
+            links: Vec::new(),
+            heading: None,
+            strong: false,
+            emphasis: false,
+            strikethrough: false,
+            link: None,
+            code_block: false,
+            quote: 0,
+            lists: Vec::new(),
+            item: false,
+        }
+    }
+
+    #[must_use]
+    fn format(&self, code: bool) -> TextFormat {
+        // Same steps between body and heading sizes as the Markdown viewer
+        let size = match self.heading {
+            Some(HeadingLevel::H1) => self.heading_size,
+            Some(level) => {
+                let step = [0.835, 0.668, 0.501, 0.334, 0.167][level as usize - 2];
+                self.body.size + (self.heading_size - self.body.size) * step
+            }
+            None => self.body.size,
+        };
+        let font_id = if code || self.code_block {
+            FontId::new(self.monospace.size.max(size), self.monospace.family.clone())
+        } else {
+            FontId::new(size, self.body.family.clone())
+        };
+        let color = if self.link.is_some() {
+            self.visuals.hyperlink_color
+        } else if self.quote > 0 {
+            self.visuals.weak_text_color()
+        } else if self.strong || self.heading.is_some_and(|level| level <= HeadingLevel::H3) {
+            self.visuals.strong_text_color()
+        } else {
+            self.visuals.text_color()
+        };
+        TextFormat {
+            background: if code || self.code_block {
+                self.visuals.code_bg_color
+            } else {
+                Color32::TRANSPARENT
+            },
+            italics: self.emphasis,
+            underline: if self.link.is_some() {
+                Stroke::new(1.0, color)
+            } else {
+                Stroke::NONE
+            },
+            strikethrough: if self.strikethrough {
+                Stroke::new(1.0, color)
+            } else {
+                Stroke::NONE
+            },
+            ..TextFormat::simple(font_id, color)
+        }
+    }
+
+    /// Appends `content`, highlighting matches when it sits at `source` in the Markdown
+    fn text(&mut self, content: &str, source: Option<usize>, code: bool) {
+        self.item = false;
+        let format = self.format(code);
+        let mut pos = 0;
+        if let Some(source) = source {
+            let end = source + content.len();
+            for (range, current) in self.hits.iter() {
+                if range.end <= source || range.start >= end {
+                    continue;
+                }
+                let start = range.start.saturating_sub(source).max(pos);
+                let stop = (range.end - source).min(content.len());
+                if start >= stop {
+                    continue;
+                }
+                self.job.append(&content[pos..start], 0.0, format.clone());
+                if *current && self.current.is_none() {
+                    self.current = Some(self.job.text.chars().count());
+                }
+                let background = if *current {
+                    self.visuals.warn_fg_color.gamma_multiply(0.6)
+                } else {
+                    self.visuals.selection.bg_fill
+                };
+                self.job.append(
+                    &content[start..stop],
+                    0.0,
+                    TextFormat {
+                        background,
+                        ..format.clone()
+                    },
+                );
+                pos = stop;
+            }
+        }
+        self.job.append(&content[pos..], 0.0, format);
+    }
+
+    fn line_break(&mut self) {
+        if !self.job.text.is_empty() && !self.job.text.ends_with('\n') {
+            self.job.append("\n", 0.0, self.format(false));
+        }
+    }
+
+    /// Blank line between blocks, except inside lists
+    fn block_break(&mut self) {
+        self.line_break();
+        if !self.job.text.is_empty() && !self.job.text.ends_with("\n\n") && self.lists.is_empty() {
+            self.job.append("\n", 0.0, self.format(false));
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph if self.item => (),
+            Tag::Paragraph | Tag::HtmlBlock | Tag::Table(_) => self.block_break(),
+            Tag::Heading { level, .. } => {
+                self.block_break();
+                self.heading = Some(level);
+            }
+            Tag::BlockQuote(_) => {
+                self.block_break();
+                self.quote += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.block_break();
+                self.code_block = true;
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block_break();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.line_break();
+                let indent = "    ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}{}. ", indent, *number - 1)
+                    }
+                    _ => format!("{}• ", indent),
+                };
+                self.text(&marker, None, false);
+                self.item = true;
+            }
+            Tag::Emphasis => self.emphasis = true,
+            Tag::Strong => self.strong = true,
+            Tag::Strikethrough => self.strikethrough = true,
+            Tag::Link { dest_url, .. } => {
+                self.link = Some((self.job.text.chars().count(), dest_url.to_string()));
+            }
+            _ => (),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) => {
+                self.heading = None;
+                self.line_break();
+            }
+            TagEnd::BlockQuote(_) => self.quote = self.quote.saturating_sub(1),
+            TagEnd::CodeBlock => {
+                self.code_block = false;
+                self.line_break();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            TagEnd::TableHead | TagEnd::TableRow => self.line_break(),
+            TagEnd::TableCell => self.text("   ", None, false),
+            TagEnd::Emphasis => self.emphasis = false,
+            TagEnd::Strong => self.strong = false,
+            TagEnd::Strikethrough => self.strikethrough = false,
+            TagEnd::Link => {
+                if let Some((start, url)) = self.link.take() {
+                    self.links.push((start..self.job.text.chars().count(), url));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text of the job, and the pieces drawn with a highlight
+    fn render(text: &str, hits: &[(Range<usize>, bool)]) -> (String, Vec<String>, Option<usize>) {
+        let mut res = None;
+        let ctx = Context::default();
+        let _ = ctx.run(RawInput::default(), |ctx| {
+            CentralPanel::default().show(ctx, |ui| {
+                let layout = markdown_job(ui, text, hits, false);
+                let job = &layout.job;
+                let highlighted = job
+                    .sections
+                    .iter()
+                    .filter(|section| {
+                        section.format.background == ui.visuals().selection.bg_fill
+                            || section.format.background
+                                == ui.visuals().warn_fg_color.gamma_multiply(0.6)
+                    })
+                    .map(|section| job.text[section.byte_range.clone()].to_owned())
+                    .collect();
+                res = Some((job.text.to_owned(), highlighted, layout.current));
+            });
+        });
+        res.unwrap()
+    }
+
+    fn hits(text: &str, word: &str) -> Vec<(Range<usize>, bool)> {
+        text.match_indices(word)
+            .enumerate()
+            .map(|(idx, (start, _))| (start..start + word.len(), idx == 0))
+            .collect()
+    }
+
+    fn bar(query: &str) -> FindBar {
+        FindBar {
+            open: true,
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn search_follows_changed_turns_only() {
+        let mut turns = vec![Turn::new("Why is the sky blue?"), Turn::new("And the sea?")];
+        turns[0].answer = "Blue light scatters more.".to_string();
+        let mut find = bar("blue");
+        find.update(&turns);
+        assert_eq!(find.matches.len(), 2);
+        let digest = find.cache[1].digest;
+
+        turns[1].answer = "It reflects the blue sky.".to_string();
+        find.update(&turns);
+        assert_eq!(find.matches.len(), 3);
+        assert_ne!(find.cache[1].digest, digest);
+        assert_eq!(find.current().map(|found| found.turn), Some(0));
+
+        find.scope = FindScope::Answers;
+        find.update(&turns);
+        assert!(find.matches.iter().all(|found| !found.prompt));
+        assert_eq!(find.matches.len(), 2);
+
+        turns.pop();
+        find.update(&turns);
+        assert_eq!(find.matches.len(), 1);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        let mut find = bar("(blue");
+        find.regex = true;
+        find.update(&[Turn::new("(blue)")]);
+        assert!(find.error.is_some());
+        assert!(find.matches.is_empty());
+    }
+
+    #[test]
+    fn markup_is_laid_out_not_shown() {
+        let text = "# Sky\n\nIt is **blue** and *wide*.\n\n- one\n- two\n\n1. first\n2. second\n";
+        let (laid, _, _) = render(text, &[]);
+        assert_eq!(
+            laid,
+            "Sky\n\nIt is blue and wide.\n\n• one\n• two\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn matches_are_highlighted_in_styled_text() {
+        let text = "# Blue sky\n\nThe sky is **blue** and `blue`.";
+        let found = hits(text, "blue");
+        assert_eq!(found.len(), 2);
+        let (laid, highlighted, current) = render(text, &found);
+        assert_eq!(highlighted, vec!["blue", "blue"]);
+        // The first match, in bold, is the current one
+        assert_eq!(current, Some(laid.find("blue").unwrap()));
+    }
+
+    #[test]
+    fn match_across_styles() {
+        let text = "The *sky* is";
+        let (_, highlighted, _) = render(text, &[(2..8, true)]);
+        assert_eq!(highlighted, vec!["e ", "sky"]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use super::LlamaApp;
+use crate::fonts::{
+    initialize_fonts, set_font_sizes, system_fonts, FontSettings, MAX_ZOOM, MIN_ZOOM,
+};
+use eframe::egui::*;
+
+const ZOOM_STEP: f32 = 0.1;
+
+#[derive(Debug, Default)]
+pub(super) struct FontsUi {
+    pub(super) settings: FontSettings,
+    open: bool,
+    system: Option<Vec<PathBuf>>,
+}
+
+impl LlamaApp {
+    pub(super) fn open_font_settings(&mut self) {
+        self.fonts.open = true;
+    }
+
+    /// Loads the configured font faces and sizes into the context
+    pub(super) fn apply_fonts(&self, ctx: &Context) {
+        match initialize_fonts(&self.fonts.settings) {
+            Ok(fonts) => ctx.set_fonts(fonts),
+            Err(err) => eprintln!("error loading fonts: {:?}", err),
+        }
+        set_font_sizes(ctx, &self.fonts.settings);
+    }
+
+    /// Steps the zoom in or out, or resets it with `None`
+    pub(super) fn zoom(&mut self, ctx: &Context, steps: Option<f32>) {
+        let zoom = match steps {
+            Some(steps) => (self.fonts.settings.zoom + steps * ZOOM_STEP).clamp(MIN_ZOOM, MAX_ZOOM),
+            None => 1.0,
+        };
+        self.fonts.settings.zoom = (zoom / ZOOM_STEP).round() * ZOOM_STEP;
+        set_font_sizes(ctx, &self.fonts.settings);
+    }
+
+    pub(super) fn show_font_settings(&mut self, ctx: &Context) {
+        if !self.fonts.open {
+            return;
+        }
+        let system = self.fonts.system.get_or_insert_with(system_fonts).clone();
+        let previous = self.fonts.settings.clone();
+        let mut open = true;
+        Window::new("Fonts")
+            .open(&mut open)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                let settings = &mut self.fonts.settings;
+                Grid::new("font-settings").num_columns(3).show(ui, |ui| {
+                    for (label, face, size) in [
+                        ("Body", &mut settings.body, &mut settings.body_size),
+                        (
+                            "Monospace",
+                            &mut settings.monospace,
+                            &mut settings.monospace_size,
+                        ),
+                        ("Heading", &mut settings.heading, &mut settings.heading_size),
+                    ] {
+                        ui.label(RichText::new(label).strong());
+                        font_picker(ui, label, face, &system);
+                        ui.add(Slider::new(size, 8.0..=48.0).step_by(1.0).suffix("pt"));
+                        ui.end_row();
+                    }
+                    ui.label(RichText::new("Zoom").strong());
+                    ui.add(
+                        Slider::new(&mut settings.zoom, MIN_ZOOM..=MAX_ZOOM)
+                            .step_by(ZOOM_STEP as f64)
+                            .custom_formatter(|value, _| format!("{:.0}%", value * 100.0)),
+                    );
+                    ui.end_row();
+                });
+                ui.separator();
+                if ui.button("Reset to defaults").clicked() {
+                    *settings = FontSettings::default();
+                }
+            });
+        self.fonts.open = open;
+
+        let settings = &self.fonts.settings;
+        if settings != &previous {
+            if settings.body != previous.body
+                || settings.monospace != previous.monospace
+                || settings.heading != previous.heading
+            {
+                self.apply_fonts(ctx);
+            } else {
+                set_font_sizes(ctx, settings);
+            }
+        }
+    }
+}
+
+fn font_picker(ui: &mut Ui, label: &str, face: &mut Option<String>, system: &[PathBuf]) {
+    let name = |path: &str| {
+        PathBuf::from(path)
+            .file_stem()
+            .and_then(|e| e.to_str())
+            .unwrap_or(path)
+            .to_string()
+    };
+    ComboBox::from_id_salt(format!("font-{}", label))
+        .width(240.0)
+        .selected_text(face.as_deref().map(name).unwrap_or("(bundled)".to_string()))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(face, None, "(bundled)");
+            for path in system.iter() {
+                let path = path.to_string_lossy().to_string();
+                let text = name(&path);
+                ui.selectable_value(face, Some(path), text);
+            }
+        });
+}
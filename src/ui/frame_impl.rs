@@ -1,29 +1,35 @@
+use super::keymap::Action;
 use super::BoxLayout;
-use super::RUNTIME;
-use crate::fonts::set_font_size;
-use crate::logics::Sender;
 use crate::logics::*;
 use eframe::Frame;
 use eframe::*;
 use egui::*;
-use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
 
 impl App for super::LlamaApp {
+    fn save(&mut self, storage: &mut dyn Storage) {
+        if let Some(positions) = self.output.encode(&STATE.read().recent) {
+            storage.set_string("scroll-positions", positions);
+        }
+    }
+
+    fn on_exit(&mut self, _gl: Option<&glow::Context>) {
+        journal::finish();
+        ipc::finish();
+    }
+
     fn update(&mut self, ctx: &Context, frame: &mut Frame) {
-        ctx.set_visuals(Visuals::dark());
-        let mut send_clicked = false;
-        let mut quit_clicked = false;
-        let mut new_clicked = false;
-        let mut load_clicked = false;
-        let mut save_clicked = false;
+        let mut actions: Vec<Action> = Vec::new();
+        let mut input: Option<Response> = None;
 
-        set_font_size(ctx, 20.0);
         self.setup(frame);
+        self.apply_theme(ctx);
+        self.update_window_title(ctx);
+        self.run_remote_commands(ctx);
         let retrieving = STATE.read().retrieving;
 
         TopBottomPanel::top("header")
             .exact_height(48.0)
-            .show(ctx, |ui| {
+            .show_animated(ctx, !self.panes.focus, |ui| {
                 ui.columns(3, |cols| {
                     cols[0].with_layout(Layout::left_to_right(Align::Center), |ui| {
                         ui.menu_button("File", |ui| {
@@ -31,44 +37,113 @@ impl App for super::LlamaApp {
                                 let _ = ui.label(RichText::new("Load").weak());
                                 let _ = ui.label(RichText::new("Save").weak());
                             } else {
-                                load_clicked = Button::new(RichText::new("Load").strong())
-                                    .shortcut_text(format!("{}O", CMD))
-                                    .ui(ui)
-                                    .clicked();
+                                self.menu_item(ui, "Load", Action::Load, &mut actions);
+                                self.menu_item(ui, "Library…", Action::Library, &mut actions);
 
-                                if STATE.read().output.is_empty() {
+                                if STATE.read().turns.is_empty() {
                                     let _ = ui.label(RichText::new("Save").weak());
                                 } else {
-                                    save_clicked = Button::new(RichText::new("Save").strong())
-                                        .shortcut_text(format!("{}S", CMD))
-                                        .ui(ui)
-                                        .clicked();
+                                    self.menu_item(ui, "Save", Action::Save, &mut actions);
                                 }
                             }
 
+                            let mut export_thinking = STATE.read().export_thinking;
+                            if ui
+                                .checkbox(&mut export_thinking, "Include thinking in exports")
+                                .changed()
+                            {
+                                STATE.write().export_thinking = export_thinking;
+                            }
+
                             ui.separator();
 
-                            quit_clicked = Button::new(RichText::new("Quit").strong())
-                                .shortcut_text(format!("{}Q", CMD))
-                                .ui(ui)
-                                .clicked();
+                            self.menu_item(ui, "Preferences…", Action::Preferences, &mut actions);
+                            self.menu_item(ui, "Quit", Action::Quit, &mut actions);
                         });
                         ui.menu_button("Actions", |ui| {
                             if retrieving {
                                 let _ = ui.label(RichText::new("New").weak());
-                                let _ = ui.label(RichText::new("Send").weak());
+                                self.menu_item(ui, "Queue", Action::Send, &mut actions);
                             } else {
-                                new_clicked = Button::new(RichText::new("New").strong())
-                                    .shortcut_text(format!("{}N", CMD))
-                                    .ui(ui)
-                                    .clicked();
+                                self.menu_item(ui, "New", Action::New, &mut actions);
+                                self.menu_item(ui, "Send", Action::Send, &mut actions);
+                            }
+
+                            ui.separator();
 
-                                send_clicked = Button::new(RichText::new("Send").strong())
-                                    .shortcut_text(format!("{}Enter", CMD))
-                                    .ui(ui)
-                                    .clicked();
+                            self.menu_item(
+                                ui,
+                                "System prompt…",
+                                Action::SystemPrompt,
+                                &mut actions,
+                            );
+                            self.menu_item(
+                                ui,
+                                "Context window…",
+                                Action::Context,
+                                &mut actions,
+                            );
+                            self.menu_item(ui, "Find…", Action::Find, &mut actions);
+                            self.menu_item(
+                                ui,
+                                "Compare models…",
+                                Action::Compare,
+                                &mut actions,
+                            );
+                            self.menu_item(
+                                ui,
+                                "Preload model",
+                                Action::Preload,
+                                &mut actions,
+                            );
+                            self.menu_item(
+                                ui,
+                                "Command palette…",
+                                Action::CommandPalette,
+                                &mut actions,
+                            );
+                            self.menu_item(
+                                ui,
+                                "Keyboard shortcuts…",
+                                Action::Shortcuts,
+                                &mut actions,
+                            );
+
+                            ui.separator();
+
+                            let mut notifications = STATE.read().notifications;
+                            if ui
+                                .checkbox(&mut notifications, "Notify when done")
+                                .on_hover_text("Desktop notification when an answer finishes while the window is in the background")
+                                .changed()
+                            {
+                                STATE.write().notifications = notifications;
                             }
                         });
+                        ui.menu_button("View", |ui| {
+                            self.menu_item(ui, "Theme…", Action::Theme, &mut actions);
+                            self.menu_item(ui, "Fonts…", Action::Fonts, &mut actions);
+                            self.menu_item(ui, "Zoom in", Action::ZoomIn, &mut actions);
+                            self.menu_item(ui, "Zoom out", Action::ZoomOut, &mut actions);
+                            self.menu_item(ui, "Reset zoom", Action::ZoomReset, &mut actions);
+                            ui.separator();
+                            self.menu_item(
+                                ui,
+                                "Vertical layout",
+                                Action::LayoutVertical,
+                                &mut actions,
+                            );
+                            self.menu_item(
+                                ui,
+                                "Horizontal layout",
+                                Action::LayoutHorizontal,
+                                &mut actions,
+                            );
+                        });
+                        ui.menu_button("Templates", |ui| {
+                            self.menu_item(ui, "Insert…", Action::InsertTemplate, &mut actions);
+                            self.menu_item(ui, "Manage…", Action::ManageTemplates, &mut actions);
+                        });
                     });
 
                     cols[1].with_layout(Layout::top_down_justified(Align::Center), |ui| {
@@ -86,7 +161,7 @@ impl App for super::LlamaApp {
                                 );
 
                                 ui.label(
-                                    RichText::new(format!("v{}", VERSION.to_string()))
+                                    RichText::new(format!("v{}", VERSION))
                                         .font(self.small_font.clone()),
                                 );
                             });
@@ -94,37 +169,14 @@ impl App for super::LlamaApp {
                     });
 
                     cols[2].with_layout(Layout::right_to_left(Align::Center), |ui| {
-                        let mut state = STATE.write();
-                        ComboBox::from_label(
-                            RichText::new("Model:")
-                                .font(self.title_font.clone())
-                                .color(Color32::from_rgb(0x54, 0x10, 0x21))
-                                .strong(),
-                        )
-                        .selected_text(&state.models[state.selected_model])
-                        .show_ui(ui, |ui| {
-                            let mut selected = state.selected_model;
-                            for (idx, opt) in state.models.iter().enumerate() {
-                                let value = ui.selectable_value(&mut selected, idx, opt.to_owned());
-                                if value.clicked() {
-                                    selected = idx;
-                                }
-                            }
-                            if selected != state.selected_model {
-                                state.selected_model = selected;
-                                if let Some(storage) = frame.storage_mut() {
-                                    storage.set_string("selected-model", format!("{}", selected));
-                                    storage.flush();
-                                }
-                            }
-                        });
+                        self.show_model_picker(ui);
                     });
                 });
             });
 
         TopBottomPanel::bottom("footer")
             .exact_height(32.0)
-            .show(ctx, |ui| {
+            .show_animated(ctx, !self.panes.focus, |ui| {
                 ui.columns(16, |cols| {
                     cols[1].with_layout(Layout::right_to_left(Align::Min), |ui| {
                         let text = RichText::new("Timeout:").strong();
@@ -144,16 +196,7 @@ impl App for super::LlamaApp {
                                             idx = i;
                                         }
                                     }
-                                    if idx != state.timeout_idx {
-                                        state.timeout_idx = idx;
-                                        if let Some(storage) = frame.storage_mut() {
-                                            storage.set_string(
-                                                "timeout",
-                                                format!("{}", TIMEOUTS[idx]),
-                                            );
-                                            storage.flush();
-                                        }
-                                    }
+                                    state.timeout_idx = idx;
                                 });
                         }
                     });
@@ -187,6 +230,30 @@ impl App for super::LlamaApp {
                         );
                     });
 
+                    cols[11].with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        let label = |think: Option<bool>| match think {
+                            None => "default",
+                            Some(true) => "on",
+                            Some(false) => "off",
+                        };
+                        let text = RichText::new("Think:").strong();
+                        let mut state = STATE.write();
+                        if retrieving {
+                            ui.label(label(state.think));
+                            ui.label(text);
+                        } else {
+                            let mut think = state.think;
+                            ComboBox::from_label(text)
+                                .selected_text(label(think))
+                                .show_ui(ui, |ui| {
+                                    for value in [None, Some(true), Some(false)] {
+                                        ui.selectable_value(&mut think, value, label(value));
+                                    }
+                                });
+                            state.think = think;
+                        }
+                    });
+
                     cols[14].with_layout(Layout::right_to_left(Align::Center), |ui| {
                         if ImageButton::new(
                             Image::new(self.vertical.clone())
@@ -195,11 +262,7 @@ impl App for super::LlamaApp {
                         .ui(ui)
                         .clicked()
                         {
-                            self.box_layout = BoxLayout::Vertically;
-                            if let Some(storage) = frame.storage_mut() {
-                                storage.set_string("layout", "V".to_string());
-                                storage.flush();
-                            }
+                            actions.push(Action::LayoutVertical);
                         }
                     });
 
@@ -211,69 +274,45 @@ impl App for super::LlamaApp {
                         .ui(ui)
                         .clicked()
                         {
-                            self.box_layout = BoxLayout::Horizontally;
-                            if let Some(storage) = frame.storage_mut() {
-                                storage.set_string("layout", "H".to_string());
-                                storage.flush();
-                            }
+                            actions.push(Action::LayoutHorizontal);
                         }
                     });
                 });
             });
 
+        self.show_queue(ctx);
+
+        if self.panes.compact {
+            let _ = input.insert(self.show_chat_box(ctx));
+        }
+
         CentralPanel::default().show(ctx, |ui| {
             let size = ui.available_size();
             let mut body: Option<Rect> = None;
-            let mut input: Option<Response> = None;
 
-            match self.box_layout {
-                BoxLayout::Horizontally => {
-                    // Dispose text viewers horizontally
-                    let text_size = Vec2::new(size.x * 3.0 / 7.0, size.y);
-                    ui.horizontal_top(|ui| {
-                        ScrollArea::vertical()
-                            .max_width(text_size.x)
-                            .max_height(text_size.y)
-                            .auto_shrink([false; 2])
-                            .show(ui, |ui| {
-                                let _ = input.insert(ui.add_sized(
-                                    text_size,
-                                    TextEdit::multiline(&mut STATE.write().input),
-                                ));
-                            });
-
-                        body = Some(ui.available_rect_before_wrap());
-                        CommonMarkViewer::default().show_scrollable(
-                            "output",
-                            ui,
-                            &mut MD_CACHE.write(),
-                            &STATE.read().output,
-                        );
-                    });
-                }
-                BoxLayout::Vertically => {
-                    // Dispose text viewers vertically (default)
-                    let text_size = Vec2::new(size.x, size.y / 3.0);
-                    ScrollArea::vertical()
-                        .max_width(text_size.x)
-                        .max_height(text_size.y)
-                        .auto_shrink([false; 2])
-                        .show(ui, |ui| {
-                            let _ = input.insert(ui.add_sized(
-                                text_size,
-                                TextEdit::multiline(&mut STATE.write().input),
-                            ));
+            if self.panes.compact {
+                body = Some(ui.available_rect_before_wrap());
+                self.show_output(ui);
+            } else {
+                match self.box_layout {
+                    BoxLayout::Horizontally => {
+                        // Dispose text viewers horizontally
+                        ui.horizontal_top(|ui| {
+                            let _ = input.insert(self.show_input(ui, size));
+                            self.show_splitter(ui, size);
+                            body = Some(ui.available_rect_before_wrap());
+                            self.show_output(ui);
                         });
-
-                    body = Some(ui.available_rect_before_wrap());
-                    CommonMarkViewer::default().show_scrollable(
-                        "output",
-                        ui,
-                        &mut MD_CACHE.write(),
-                        &STATE.read().output,
-                    );
+                    }
+                    BoxLayout::Vertically => {
+                        // Dispose text viewers vertically (default)
+                        let _ = input.insert(self.show_input(ui, size));
+                        self.show_splitter(ui, size);
+                        body = Some(ui.available_rect_before_wrap());
+                        self.show_output(ui);
+                    }
+                    BoxLayout::NotSet => (),
                 }
-                BoxLayout::NotSet => (),
             }
 
             if retrieving {
@@ -297,61 +336,63 @@ impl App for super::LlamaApp {
                 Spinner::new().paint_at(ui, Rect::from_min_max(min, max));
             }
 
-            if self.temperature != self.last_temperature {
-                if let Some(storage) = frame.storage_mut() {
-                    storage.set_string("temperature", format!("{}", self.temperature));
-                    storage.flush();
-                }
-                self.last_temperature = self.temperature;
-            }
-
             if STATE.read().reload {
                 STATE.write().reload = false;
                 if let Some(storage) = frame.storage_mut() {
-                    storage.set_string("cwd", STATE.read().cwd.to_owned());
+                    if let Ok(recent) = serde_json::to_string(&STATE.read().recent) {
+                        storage.set_string("recent", recent);
+                    }
                     storage.flush();
                 }
-                if let Some(input) = input {
+                if let Some(input) = input.as_ref() {
                     input.request_focus();
                 }
             }
         });
 
-        if retrieving {
-            if ctx.input(|st| st.key_pressed(Key::Escape)) {
-                STATE.write().escape = true;
-            }
-        } else {
-            if new_clicked || ctx.input(|rd| rd.modifiers.command && rd.key_pressed(Key::N)) {
-                STATE.write().reset();
-            }
-            if load_clicked || ctx.input(|rd| rd.modifiers.command && rd.key_pressed(Key::O)) {
-                RUNTIME.spawn(storage::load());
-            }
-            if save_clicked
-                || (!STATE.read().output.is_empty()
-                    && ctx.input(|rd| rd.modifiers.command && rd.key_pressed(Key::S)))
-            {
-                RUNTIME.spawn(storage::save_content(STATE.read().output.to_owned()));
-            }
-            if send_clicked || ctx.input(|rd| rd.modifiers.command && rd.key_pressed(Key::Enter)) {
-                RUNTIME.spawn(Sender::new(self.temperature).send());
-            }
+        if self.system_open {
+            let mut open = true;
+            Window::new("System Prompt")
+                .open(&mut open)
+                .default_width(480.0)
+                .show(ctx, |ui| {
+                    ui.add(
+                        TextEdit::multiline(&mut STATE.write().system)
+                            .hint_text("Instructions sent to the model with every prompt")
+                            .desired_width(f32::INFINITY),
+                    );
+                });
+            self.system_open = open;
         }
 
-        if quit_clicked || ctx.input(|rd| rd.modifiers.command && rd.key_pressed(Key::Q)) {
-            ctx.send_viewport_cmd(ViewportCommand::Close);
+        if self.panes.focus {
+            self.show_focus_exit(ctx);
+        }
+        if let Some(input) = input.as_ref() {
+            self.recall_history(ctx, input);
         }
+        self.show_history_search(ctx);
+        self.show_recovery(ctx);
+        self.show_missing_model(ctx);
+        self.preload_selected();
+        self.show_compare(ctx);
+        self.show_context(ctx);
+        self.show_library(ctx);
+        self.show_templates(ctx);
+        self.show_shortcuts(ctx);
+        self.show_palette(ctx);
+        self.show_theme_editor(ctx);
+        self.show_font_settings(ctx);
+        self.show_preferences(ctx);
 
-        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+        if !self.capturing_keys() {
+            actions.extend(ctx.input(|rd| self.keymap.triggered(rd)));
+        }
+        for action in actions {
+            self.run_action(ctx, action);
+        }
+        self.run_queue();
+        self.autosave(ctx);
+        self.store_settings(ctx, frame);
     }
 }
-
-#[dynamic]
-static mut MD_CACHE: CommonMarkCache = CommonMarkCache::default();
-
-#[cfg(not(target_os = "macos"))]
-static CMD: &str = "Ctrl+";
-
-#[cfg(target_os = "macos")]
-static CMD: &str = "⌘";
@@ -0,0 +1,154 @@
+use super::{replace_input, LlamaApp};
+use crate::logics::STATE;
+use eframe::egui::*;
+
+const MAX_RESULTS: usize = 12;
+
+/// Walking through earlier prompts from the input
+#[derive(Debug, Default)]
+pub(super) struct History {
+    position: Option<usize>,
+    recalled: String,
+    search: Option<Search>,
+}
+
+#[derive(Debug, Default)]
+struct Search {
+    query: String,
+    selected: usize,
+}
+
+/// Prompts of this conversation, then the global ones, newest first
+fn entries() -> Vec<String> {
+    let state = STATE.read();
+    let mut res: Vec<String> = Vec::new();
+    let conversation = state.turns.iter().rev().map(|turn| &turn.prompt);
+    for prompt in conversation.chain(state.history.iter().rev()) {
+        if !prompt.is_empty() && !res.contains(prompt) {
+            res.push(prompt.to_owned());
+        }
+    }
+    res
+}
+
+impl LlamaApp {
+    /// Up and Down recall earlier prompts while the input is empty or
+    /// still holds a recalled one
+    pub(super) fn recall_history(&mut self, ctx: &Context, input: &Response) {
+        if !input.has_focus() || self.history.search.is_some() {
+            return;
+        }
+        let (up, down) = ctx.input(|rd| {
+            (
+                rd.modifiers.is_none() && rd.key_pressed(Key::ArrowUp),
+                rd.modifiers.is_none() && rd.key_pressed(Key::ArrowDown),
+            )
+        });
+        let walking = {
+            let state = STATE.read();
+            state.input.is_empty()
+                || (self.history.position.is_some() && state.input == self.history.recalled)
+        };
+        if !walking {
+            self.history.position = None;
+            return;
+        }
+        if !up && !down {
+            return;
+        }
+
+        let entries = entries();
+        let position = match (self.history.position, up) {
+            (None, true) if !entries.is_empty() => Some(0),
+            (None, _) => return,
+            (Some(pos), true) => Some((pos + 1).min(entries.len().saturating_sub(1))),
+            (Some(0), false) => None,
+            (Some(pos), false) => Some(pos - 1),
+        };
+        self.history.position = position;
+        self.history.recalled = position
+            .and_then(|pos| entries.get(pos).cloned())
+            .unwrap_or_default();
+        replace_input(ctx, &self.history.recalled);
+    }
+
+    /// Opens the reverse search, or steps to an older match if already open
+    pub(super) fn search_history(&mut self) {
+        match self.history.search.as_mut() {
+            Some(search) => search.selected += 1,
+            None => self.history.search = Some(Search::default()),
+        }
+    }
+
+    pub(super) fn show_history_search(&mut self, ctx: &Context) {
+        let Some(search) = self.history.search.as_mut() else {
+            return;
+        };
+        let query = search.query.to_lowercase();
+        let results = entries()
+            .into_iter()
+            .filter(|prompt| prompt.to_lowercase().contains(&query))
+            .take(MAX_RESULTS)
+            .collect::<Vec<_>>();
+        // Escape is taken, so it doesn't stop a running answer too
+        let (up, down, enter, escape) = ctx.input_mut(|rd| {
+            (
+                rd.key_pressed(Key::ArrowUp),
+                rd.key_pressed(Key::ArrowDown),
+                rd.key_pressed(Key::Enter),
+                rd.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if up {
+            search.selected += 1;
+        }
+        if down {
+            search.selected = search.selected.saturating_sub(1);
+        }
+        search.selected = search.selected.min(results.len().saturating_sub(1));
+
+        let mut chosen: Option<String> = None;
+        Window::new("History Search")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .fixed_size(Vec2::new(480.0, 0.0))
+            .anchor(Align2::CENTER_BOTTOM, Vec2::new(0.0, -64.0))
+            .show(ctx, |ui| {
+                // Newest at the bottom, like a shell
+                for (idx, prompt) in results.iter().enumerate().rev() {
+                    let text = prompt.lines().next().unwrap_or_default();
+                    if ui
+                        .selectable_label(idx == search.selected, text)
+                        .on_hover_text(prompt)
+                        .clicked()
+                    {
+                        chosen = Some(prompt.to_owned());
+                    }
+                }
+                if results.is_empty() {
+                    ui.label(RichText::new("No match").weak());
+                }
+                ui.separator();
+                let response = ui.add(
+                    TextEdit::singleline(&mut search.query)
+                        .hint_text("Search prompt history")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    search.selected = 0;
+                }
+            });
+
+        if enter && chosen.is_none() {
+            chosen = results.get(search.selected).cloned();
+        }
+        if let Some(prompt) = chosen {
+            self.history.search = None;
+            replace_input(ctx, &prompt);
+        } else if escape {
+            self.history.search = None;
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+
+use eframe::egui::{Context, Event, InputState, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Action {
+    New,
+    Load,
+    Save,
+    Send,
+    Stop,
+    Quit,
+    SystemPrompt,
+    Context,
+    InsertTemplate,
+    ManageTemplates,
+    CommandPalette,
+    Compare,
+    Preload,
+    Library,
+    Find,
+    HistorySearch,
+    Shortcuts,
+    Theme,
+    Fonts,
+    Preferences,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    CompactInput,
+    FocusMode,
+    LayoutVertical,
+    LayoutHorizontal,
+}
+
+impl Action {
+    pub(super) const ALL: &'static [Action] = &[
+        Action::New,
+        Action::Load,
+        Action::Save,
+        Action::Send,
+        Action::Stop,
+        Action::Quit,
+        Action::SystemPrompt,
+        Action::Context,
+        Action::InsertTemplate,
+        Action::ManageTemplates,
+        Action::CommandPalette,
+        Action::Compare,
+        Action::Preload,
+        Action::Library,
+        Action::Find,
+        Action::HistorySearch,
+        Action::Shortcuts,
+        Action::Theme,
+        Action::Fonts,
+        Action::Preferences,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomReset,
+        Action::CompactInput,
+        Action::FocusMode,
+        Action::LayoutVertical,
+        Action::LayoutHorizontal,
+    ];
+
+    #[must_use]
+    pub(super) fn label(&self) -> &'static str {
+        match self {
+            Action::New => "New conversation",
+            Action::Load => "Load conversation",
+            Action::Save => "Save conversation",
+            Action::Send => "Send prompt",
+            Action::Stop => "Stop generation",
+            Action::Quit => "Quit",
+            Action::SystemPrompt => "Edit system prompt",
+            Action::Context => "Context window",
+            Action::InsertTemplate => "Insert template",
+            Action::ManageTemplates => "Manage templates",
+            Action::CommandPalette => "Command palette",
+            Action::Compare => "Compare models",
+            Action::Preload => "Preload model",
+            Action::Library => "Conversation library",
+            Action::Find => "Find in conversation",
+            Action::HistorySearch => "Search prompt history",
+            Action::Shortcuts => "Keyboard shortcuts",
+            Action::Theme => "Theme settings",
+            Action::Fonts => "Font settings",
+            Action::Preferences => "Preferences",
+            Action::ZoomIn => "Zoom in",
+            Action::ZoomOut => "Zoom out",
+            Action::ZoomReset => "Reset zoom",
+            Action::CompactInput => "Toggle compact input",
+            Action::FocusMode => "Toggle focus mode",
+            Action::LayoutVertical => "Layout: vertical",
+            Action::LayoutHorizontal => "Layout: horizontal",
+        }
+    }
+
+    #[must_use]
+    fn default_shortcut(&self) -> Option<KeyboardShortcut> {
+        let cmd = |key| Some(KeyboardShortcut::new(Modifiers::COMMAND, key));
+        match self {
+            Action::New => cmd(Key::N),
+            Action::Load => cmd(Key::O),
+            Action::Library => Some(KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::O,
+            )),
+            Action::Save => cmd(Key::S),
+            Action::Send => cmd(Key::Enter),
+            Action::Stop => Some(KeyboardShortcut::new(Modifiers::NONE, Key::Escape)),
+            Action::Quit => cmd(Key::Q),
+            Action::Preferences => cmd(Key::Comma),
+            Action::InsertTemplate => cmd(Key::T),
+            Action::Find => cmd(Key::F),
+            Action::HistorySearch => Some(KeyboardShortcut::new(Modifiers::CTRL, Key::R)),
+            Action::ZoomIn => cmd(Key::Plus),
+            Action::ZoomOut => cmd(Key::Minus),
+            Action::ZoomReset => cmd(Key::Num0),
+            Action::FocusMode => Some(KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::F,
+            )),
+            Action::CommandPalette => Some(KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::P,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Maps each action to an optional key chord, kept as text such as `Cmd+Shift+O`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "BTreeMap<Action, String>", into = "BTreeMap<Action, String>")]
+pub(super) struct Keymap(BTreeMap<Action, Option<KeyboardShortcut>>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_shortcut()))
+                .collect(),
+        )
+    }
+}
+
+/// Unbound actions are kept as an empty chord, so they don't get their default back
+impl From<BTreeMap<Action, String>> for Keymap {
+    fn from(chords: BTreeMap<Action, String>) -> Self {
+        let mut keymap = Self::default();
+        for (action, chord) in chords {
+            if chord.is_empty() {
+                keymap.0.insert(action, None);
+            } else if let Some(shortcut) = parse_chord(&chord) {
+                keymap.0.insert(action, Some(shortcut));
+            } else {
+                warn!("ignoring unknown chord {:?} for {:?}", chord, action);
+            }
+        }
+        keymap
+    }
+}
+
+impl From<Keymap> for BTreeMap<Action, String> {
+    fn from(keymap: Keymap) -> Self {
+        keymap
+            .0
+            .into_iter()
+            .map(|(action, shortcut)| (action, shortcut.map(chord_text).unwrap_or_default()))
+            .collect()
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap stored by older versions, filling in defaults for actions it doesn't know
+    #[must_use]
+    pub(super) fn decode(content: &str) -> Self {
+        let mut keymap = Self::default();
+        match serde_json::from_str::<BTreeMap<Action, Option<KeyboardShortcut>>>(content) {
+            Ok(stored) => keymap.0.extend(stored),
+            Err(err) => eprintln!("error reading keymap: {:?}", err),
+        }
+        keymap
+    }
+
+    #[must_use]
+    pub(super) fn get(&self, action: Action) -> Option<KeyboardShortcut> {
+        self.0.get(&action).copied().flatten()
+    }
+
+    pub(super) fn set(&mut self, action: Action, shortcut: Option<KeyboardShortcut>) {
+        self.0.insert(action, shortcut);
+    }
+
+    pub(super) fn reset(&mut self, action: Action) {
+        self.0.insert(action, action.default_shortcut());
+    }
+
+    /// Human readable chord for menus, empty when unbound
+    #[must_use]
+    pub(super) fn label(&self, ctx: &Context, action: Action) -> String {
+        self.get(action)
+            .map(|shortcut| ctx.format_shortcut(&shortcut))
+            .unwrap_or_default()
+    }
+
+    /// Other actions bound to the same chord
+    #[must_use]
+    pub(super) fn conflicts(&self, action: Action) -> Vec<Action> {
+        let Some(shortcut) = self.get(action) else {
+            return Vec::new();
+        };
+        self.0
+            .iter()
+            .filter(|(other, sc)| **other != action && **sc == Some(shortcut))
+            .map(|(other, _)| *other)
+            .collect()
+    }
+
+    /// Actions whose chord was pressed in this frame
+    #[must_use]
+    pub(super) fn triggered(&self, input: &InputState) -> Vec<Action> {
+        self.0
+            .iter()
+            .filter_map(|(action, shortcut)| {
+                let shortcut = (*shortcut)?;
+                // Plus usually needs Shift, or is typed as Equals on the same key,
+                // so accept both as egui's own zoom does
+                if shortcut.logical_key == Key::Plus {
+                    let pressed = input.key_pressed(Key::Plus) || input.key_pressed(Key::Equals);
+                    return (input.modifiers.matches_logically(shortcut.modifiers) && pressed)
+                        .then_some(*action);
+                }
+                (input.modifiers.matches_exact(shortcut.modifiers)
+                    && input.key_pressed(shortcut.logical_key))
+                .then_some(*action)
+            })
+            .collect()
+    }
+}
+
+/// First key chord pressed in this frame, used when rebinding
+#[must_use]
+pub(super) fn capture(input: &InputState) -> Option<KeyboardShortcut> {
+    input.events.iter().find_map(|event| match event {
+        Event::Key {
+            key,
+            pressed: true,
+            modifiers,
+            ..
+        } => Some(KeyboardShortcut::new(
+            Modifiers {
+                alt: modifiers.alt,
+                shift: modifiers.shift,
+                ctrl: modifiers.ctrl && !modifiers.command,
+                mac_cmd: false,
+                command: modifiers.command,
+            },
+            *key,
+        )),
+        _ => None,
+    })
+}
+
+#[must_use]
+fn chord_text(shortcut: KeyboardShortcut) -> String {
+    let modifiers = shortcut.modifiers;
+    let mut parts = Vec::new();
+    if modifiers.command || modifiers.mac_cmd {
+        parts.push("Cmd");
+    }
+    if modifiers.ctrl && !modifiers.command {
+        parts.push("Ctrl");
+    }
+    if modifiers.alt {
+        parts.push("Alt");
+    }
+    if modifiers.shift {
+        parts.push("Shift");
+    }
+    parts.push(shortcut.logical_key.name());
+    parts.join("+")
+}
+
+#[must_use]
+fn parse_chord(chord: &str) -> Option<KeyboardShortcut> {
+    let (modifiers, key) = match chord.rsplit_once('+') {
+        Some((modifiers, key)) => (modifiers, key),
+        None => ("", chord),
+    };
+    let mut res = Modifiers::NONE;
+    for modifier in modifiers.split('+').filter(|e| !e.is_empty()) {
+        res = res
+            | match modifier {
+                "Cmd" => Modifiers::COMMAND,
+                "Ctrl" => Modifiers::CTRL,
+                "Alt" => Modifiers::ALT,
+                "Shift" => Modifiers::SHIFT,
+                _ => return None,
+            };
+    }
+    Some(KeyboardShortcut::new(res, Key::from_name(key)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(modifiers: Modifiers, key: Key) -> InputState {
+        let mut input = InputState::default();
+        input.modifiers = modifiers;
+        input.events.push(Event::Key {
+            key,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers,
+        });
+        input
+    }
+
+    #[test]
+    fn zoom_in_with_plus_or_equals() {
+        let keymap = Keymap::default();
+        for (modifiers, key) in [
+            (Modifiers::COMMAND, Key::Plus),
+            (Modifiers::COMMAND | Modifiers::SHIFT, Key::Plus),
+            (Modifiers::COMMAND, Key::Equals),
+        ] {
+            assert_eq!(
+                keymap.triggered(&pressed(modifiers, key)),
+                vec![Action::ZoomIn]
+            );
+        }
+        assert!(keymap
+            .triggered(&pressed(Modifiers::NONE, Key::Equals))
+            .is_empty());
+    }
+}
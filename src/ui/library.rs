@@ -0,0 +1,423 @@
+use std::path::PathBuf;
+
+use super::{LlamaApp, RUNTIME};
+use crate::logics::library::{self, Entry, Meta, LIBRARY};
+use crate::logics::{storage, STATE};
+use chrono::{Duration, Local};
+use eframe::egui::*;
+use rfd::FileDialog;
+
+/// Which conversations the folder list shows
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+enum Shelf {
+    #[default]
+    All,
+    Favorites,
+    Unfiled,
+    Folder(String),
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Since {
+    #[default]
+    Ever,
+    Today,
+    Week,
+    Month,
+    Year,
+}
+
+impl Since {
+    const ALL: [Since; 5] = [
+        Since::Ever,
+        Since::Today,
+        Since::Week,
+        Since::Month,
+        Since::Year,
+    ];
+
+    #[must_use]
+    fn label(&self) -> &'static str {
+        match self {
+            Since::Ever => "Any time",
+            Since::Today => "Today",
+            Since::Week => "Last 7 days",
+            Since::Month => "Last 30 days",
+            Since::Year => "Last year",
+        }
+    }
+
+    #[must_use]
+    fn days(&self) -> Option<i64> {
+        match self {
+            Since::Ever => None,
+            Since::Today => Some(1),
+            Since::Week => Some(7),
+            Since::Month => Some(30),
+            Since::Year => Some(365),
+        }
+    }
+}
+
+/// Folder and tags being edited, as typed
+#[derive(Debug, Default)]
+struct Filing {
+    path: String,
+    folder: String,
+    tags: String,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct LibraryUi {
+    open: bool,
+    /// Directories searched for conversations
+    pub(super) roots: Vec<String>,
+    shelf: Shelf,
+    query: String,
+    model: Option<String>,
+    tag: Option<String>,
+    since: Since,
+    filing: Option<Filing>,
+    /// Filing of the current conversation, as typed
+    current: Option<(String, String)>,
+}
+
+impl LibraryUi {
+    #[must_use]
+    fn matches(&self, entry: &Entry) -> bool {
+        let shelf = match &self.shelf {
+            Shelf::All => true,
+            Shelf::Favorites => entry.meta.favorite,
+            Shelf::Unfiled => entry.meta.folder.is_empty(),
+            Shelf::Folder(folder) => &entry.meta.folder == folder,
+        };
+        let query = self.query.to_lowercase();
+        let text = query.is_empty()
+            || entry.title.to_lowercase().contains(&query)
+            || entry.path.to_lowercase().contains(&query);
+        let model = self
+            .model
+            .as_ref()
+            .is_none_or(|model| &entry.model == model);
+        let tag = self
+            .tag
+            .as_ref()
+            .is_none_or(|tag| entry.meta.tags.contains(tag));
+        let since = self.since.days().is_none_or(|days| {
+            entry
+                .date
+                .is_some_and(|date| date > Local::now() - Duration::days(days))
+        });
+        shelf && text && model && tag && since
+    }
+}
+
+/// Distinct non-empty values, sorted
+fn distinct<'a>(values: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut res = values
+        .filter(|e| !e.is_empty())
+        .cloned()
+        .collect::<Vec<_>>();
+    res.sort();
+    res.dedup();
+    res
+}
+
+fn filter_combo(ui: &mut Ui, id: &str, any: &str, value: &mut Option<String>, options: &[String]) {
+    ComboBox::from_id_salt(id)
+        .selected_text(value.as_deref().unwrap_or(any))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(value, None, any);
+            for option in options {
+                ui.selectable_value(value, Some(option.to_owned()), option);
+            }
+        });
+}
+
+/// Stores new filing for a conversation, in its file when it has one
+fn file_conversation(path: Option<&str>, meta: Meta) {
+    match path {
+        Some(path) => {
+            if let Err(err) = library::update_meta(path, &meta) {
+                eprintln!("error updating {}", path);
+                eprintln!("{:?}", err);
+            }
+        }
+        None => STATE.write().meta = meta,
+    }
+}
+
+impl LlamaApp {
+    pub(super) fn open_library(&mut self) {
+        self.library.open = true;
+        RUNTIME.spawn(library::scan(self.library.roots.clone()));
+    }
+
+    /// Star and filing of the current conversation, next to its title
+    pub(super) fn show_filing(&mut self, ui: &mut Ui) {
+        let (meta, path) = {
+            let state = STATE.read();
+            (state.meta.clone(), state.path.clone())
+        };
+        let star = if meta.favorite { "★" } else { "☆" };
+        if ui
+            .add(Button::new(star).small())
+            .on_hover_text("Favorite")
+            .clicked()
+        {
+            file_conversation(
+                path.as_deref(),
+                Meta {
+                    favorite: !meta.favorite,
+                    ..meta.clone()
+                },
+            );
+        }
+        let response = ui.menu_button("🏷", |ui| {
+            let (folder, tags) = self
+                .library
+                .current
+                .get_or_insert_with(|| (meta.folder.to_owned(), meta.tags.join(", ")));
+            Grid::new("filing").num_columns(2).show(ui, |ui| {
+                ui.label("Folder:");
+                ui.text_edit_singleline(folder);
+                ui.end_row();
+                ui.label("Tags:");
+                ui.add(TextEdit::singleline(tags).hint_text("comma separated"));
+                ui.end_row();
+            });
+            if ui.button("Apply").clicked() {
+                let mut filed = Meta {
+                    folder: folder.trim().to_owned(),
+                    ..meta.clone()
+                };
+                filed.set_tags(tags);
+                file_conversation(path.as_deref(), filed);
+                ui.close_menu();
+            }
+        });
+        response.response.on_hover_text(if meta.tags.is_empty() {
+            "Folder and tags".to_string()
+        } else {
+            meta.tags.join(", ")
+        });
+        if response.inner.is_none() {
+            self.library.current = None;
+        }
+    }
+
+    pub(super) fn show_library(&mut self, ctx: &Context) {
+        if !self.library.open {
+            return;
+        }
+        let retrieving = STATE.read().retrieving;
+        let mut open = true;
+        let mut chosen: Option<String> = None;
+        let mut roots_changed = false;
+
+        Window::new("Library")
+            .open(&mut open)
+            .default_size(Vec2::new(860.0, 520.0))
+            .show(ctx, |ui| {
+                let library = LIBRARY.read();
+                let entries = &library.entries;
+                let folders = distinct(entries.iter().map(|entry| &entry.meta.folder));
+                let models = distinct(entries.iter().map(|entry| &entry.model));
+                let tags = distinct(entries.iter().flat_map(|entry| entry.meta.tags.iter()));
+
+                ui.horizontal(|ui| {
+                    ui.add(
+                        TextEdit::singleline(&mut self.library.query)
+                            .hint_text("Search titles")
+                            .desired_width(200.0),
+                    );
+                    filter_combo(
+                        ui,
+                        "library-model",
+                        "Any model",
+                        &mut self.library.model,
+                        &models,
+                    );
+                    filter_combo(ui, "library-tag", "Any tag", &mut self.library.tag, &tags);
+                    ComboBox::from_id_salt("library-since")
+                        .selected_text(self.library.since.label())
+                        .show_ui(ui, |ui| {
+                            for since in Since::ALL {
+                                ui.selectable_value(&mut self.library.since, since, since.label());
+                            }
+                        });
+                    if library.scanning {
+                        ui.spinner();
+                    } else if ui.button("⟳").on_hover_text("Scan again").clicked() {
+                        RUNTIME.spawn(library::scan(self.library.roots.clone()));
+                    }
+                });
+                ui.separator();
+
+                SidePanel::left("library-shelves")
+                    .resizable(false)
+                    .exact_width(160.0)
+                    .show_inside(ui, |ui| {
+                        let shelf = &mut self.library.shelf;
+                        ui.selectable_value(shelf, Shelf::All, "All conversations");
+                        ui.selectable_value(shelf, Shelf::Favorites, "★ Favorites");
+                        ui.selectable_value(shelf, Shelf::Unfiled, "Unfiled");
+                        ui.separator();
+                        for folder in folders.iter() {
+                            ui.selectable_value(
+                                shelf,
+                                Shelf::Folder(folder.to_owned()),
+                                format!("🗀 {}", folder),
+                            );
+                        }
+                        ui.separator();
+                        ui.label(RichText::new("Searched in").weak());
+                        let mut removed: Option<usize> = None;
+                        for (idx, root) in self.library.roots.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("✖").clicked() {
+                                    removed = Some(idx);
+                                }
+                                ui.label(RichText::new(root).small()).on_hover_text(root);
+                            });
+                        }
+                        if let Some(idx) = removed {
+                            self.library.roots.remove(idx);
+                            roots_changed = true;
+                        }
+                        if ui.button("Add folder…").clicked() {
+                            let cwd = STATE.read().cwd.to_owned();
+                            if let Some(dir) = FileDialog::new()
+                                .set_title("Llama Desktop Library Folder")
+                                .set_directory(cwd)
+                                .pick_folder()
+                            {
+                                let dir = dir.to_string_lossy().to_string();
+                                if !self.library.roots.contains(&dir) {
+                                    self.library.roots.push(dir);
+                                    roots_changed = true;
+                                }
+                            }
+                        }
+                    });
+
+                // Some(None) cancels the edit
+                let mut edit: Option<Option<Meta>> = None;
+                if let Some(filing) = self.library.filing.as_mut() {
+                    ui.horizontal(|ui| {
+                        ui.label("Folder:");
+                        ui.add(TextEdit::singleline(&mut filing.folder).desired_width(120.0));
+                        ui.label("Tags:");
+                        ui.add(
+                            TextEdit::singleline(&mut filing.tags)
+                                .hint_text("comma separated")
+                                .desired_width(200.0),
+                        );
+                        if ui.button("Apply").clicked() {
+                            let mut meta = entries
+                                .iter()
+                                .find(|entry| entry.path == filing.path)
+                                .map(|entry| entry.meta.clone())
+                                .unwrap_or_default();
+                            meta.folder = filing.folder.trim().to_owned();
+                            meta.set_tags(&filing.tags);
+                            edit = Some(Some(meta));
+                        }
+                        if ui.button("Cancel").clicked() {
+                            edit = Some(None);
+                        }
+                    });
+                    ui.separator();
+                }
+
+                let mut star: Option<(String, Meta)> = None;
+                let mut filing: Option<Filing> = None;
+                ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        Grid::new("library")
+                            .num_columns(6)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                let shown =
+                                    entries.iter().filter(|entry| self.library.matches(entry));
+                                for entry in shown {
+                                    let icon = if entry.meta.favorite { "★" } else { "☆" };
+                                    if ui.small_button(icon).clicked() {
+                                        star = Some((
+                                            entry.path.to_owned(),
+                                            Meta {
+                                                favorite: !entry.meta.favorite,
+                                                ..entry.meta.clone()
+                                            },
+                                        ));
+                                    }
+                                    if ui
+                                        .add_enabled(!retrieving, Link::new(&entry.title))
+                                        .on_hover_text(&entry.path)
+                                        .clicked()
+                                    {
+                                        chosen = Some(entry.path.to_owned());
+                                    }
+                                    ui.label(RichText::new(&entry.model).small());
+                                    ui.label(RichText::new(&entry.meta.folder).small());
+                                    ui.label(
+                                        RichText::new(entry.meta.tags.join(", ")).small().weak(),
+                                    );
+                                    ui.horizontal(|ui| {
+                                        if let Some(date) = entry.date {
+                                            ui.label(
+                                                RichText::new(
+                                                    date.format("%Y-%m-%d %H:%M").to_string(),
+                                                )
+                                                .small(),
+                                            );
+                                        }
+                                        if ui
+                                            .small_button("✏")
+                                            .on_hover_text("Folder and tags")
+                                            .clicked()
+                                        {
+                                            filing = Some(Filing {
+                                                path: entry.path.to_owned(),
+                                                folder: entry.meta.folder.to_owned(),
+                                                tags: entry.meta.tags.join(", "),
+                                            });
+                                        }
+                                    });
+                                    ui.end_row();
+                                }
+                            });
+                        if entries.is_empty() && !library.scanning {
+                            ui.label(
+                                RichText::new("No conversations found. Add a folder to search in.")
+                                    .weak(),
+                            );
+                        }
+                    });
+                drop(library);
+
+                if let Some((path, meta)) = star {
+                    file_conversation(Some(&path), meta);
+                }
+                if let Some(edit) = edit {
+                    if let (Some(meta), Some(filing)) = (edit, self.library.filing.take()) {
+                        file_conversation(Some(&filing.path), meta);
+                    }
+                }
+                if filing.is_some() {
+                    self.library.filing = filing;
+                }
+            });
+        self.library.open = open;
+
+        if roots_changed {
+            RUNTIME.spawn(library::scan(self.library.roots.clone()));
+        }
+        if let Some(path) = chosen {
+            self.library.open = false;
+            STATE.write().reset();
+            RUNTIME.spawn(storage::load_path(PathBuf::from(path)));
+        }
+    }
+}
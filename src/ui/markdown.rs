@@ -0,0 +1,144 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::Arc,
+};
+
+use super::find::markdown_job;
+use eframe::egui::*;
+
+/// Markdown laid out once and shown again while its text, style and width
+/// stay the same, so only turns that changed are parsed
+#[derive(Debug, Default)]
+pub(super) struct MarkdownLayouts {
+    laid: HashMap<u64, Laid>,
+    /// Shown since the last `retain_shown`, the others are dropped then
+    shown: HashSet<u64>,
+}
+
+#[derive(Debug)]
+struct Laid {
+    galley: Arc<Galley>,
+    /// Character ranges of the links and where they point to
+    links: Vec<(Range<usize>, String)>,
+}
+
+impl MarkdownLayouts {
+    /// Shows `text` as Markdown, as a blockquote when `quote` is set
+    pub(super) fn show(&mut self, ui: &mut Ui, text: &str, quote: bool) {
+        let width = ui.available_width();
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        quote.hash(&mut hasher);
+        width.to_bits().hash(&mut hasher);
+        hash_style(ui, &mut hasher);
+        let key = hasher.finish();
+
+        self.shown.insert(key);
+        let laid = self.laid.entry(key).or_insert_with(|| {
+            let layout = markdown_job(ui, text, &[], quote);
+            let mut job = layout.job;
+            job.wrap.max_width = width;
+            Laid {
+                galley: ui.fonts(|fonts| fonts.layout_job(job)),
+                links: layout.links,
+            }
+        });
+
+        let response = ui.add(Label::new(laid.galley.clone()).sense(Sense::click()));
+        let Some(pos) = response.hover_pos() else {
+            return;
+        };
+        let idx = laid
+            .galley
+            .cursor_from_pos(pos - response.rect.min)
+            .ccursor
+            .index;
+        if let Some((_, url)) = laid.links.iter().find(|(range, _)| range.contains(&idx)) {
+            ui.ctx().set_cursor_icon(CursorIcon::PointingHand);
+            if response.clicked() {
+                ui.ctx().open_url(OpenUrl::new_tab(url));
+            }
+        }
+    }
+
+    /// Forgets the layouts that weren't shown since the last call
+    pub(super) fn retain_shown(&mut self) {
+        let shown = std::mem::take(&mut self.shown);
+        self.laid.retain(|key, _| shown.contains(key));
+    }
+}
+
+/// What the laid out text takes from the style: colours and fonts
+fn hash_style(ui: &Ui, hasher: &mut impl Hasher) {
+    let visuals = ui.visuals();
+    [
+        visuals.text_color(),
+        visuals.weak_text_color(),
+        visuals.strong_text_color(),
+        visuals.hyperlink_color,
+        visuals.code_bg_color,
+        visuals.selection.bg_fill,
+    ]
+    .hash(hasher);
+    for style in [TextStyle::Body, TextStyle::Monospace, TextStyle::Heading] {
+        style.resolve(ui.style()).hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shows each of `texts` in one frame, as the output pane does
+    fn frame(ctx: &Context, layouts: &mut MarkdownLayouts, texts: &[&str]) {
+        let _ = ctx.run(RawInput::default(), |ctx| {
+            CentralPanel::default().show(ctx, |ui| {
+                for text in texts {
+                    layouts.show(ui, text, false);
+                }
+            });
+        });
+        layouts.retain_shown();
+    }
+
+    #[test]
+    fn unchanged_text_is_laid_out_once() {
+        let ctx = Context::default();
+        let mut layouts = MarkdownLayouts::default();
+        frame(&ctx, &mut layouts, &["# Sky", "It is **blue**"]);
+        let galley = layouts.laid.values().next().unwrap().galley.clone();
+        frame(&ctx, &mut layouts, &["# Sky", "It is **blue**"]);
+        assert_eq!(layouts.laid.len(), 2);
+        assert!(layouts
+            .laid
+            .values()
+            .any(|laid| Arc::ptr_eq(&laid.galley, &galley)));
+
+        frame(&ctx, &mut layouts, &["# Sky", "It is **blue** and"]);
+        assert_eq!(layouts.laid.len(), 2);
+        frame(&ctx, &mut layouts, &[]);
+        assert!(layouts.laid.is_empty());
+    }
+
+    #[test]
+    fn links_keep_their_targets() {
+        let ctx = Context::default();
+        let mut layouts = MarkdownLayouts::default();
+        frame(
+            &ctx,
+            &mut layouts,
+            &["See [Ollama](https://ollama.com) and *[docs](https://docs.rs)*."],
+        );
+        let laid = layouts.laid.values().next().unwrap();
+        assert_eq!(laid.galley.text(), "See Ollama and docs.");
+        assert_eq!(
+            laid.links,
+            vec![
+                (4..10, "https://ollama.com".to_string()),
+                (15..19, "https://docs.rs".to_string()),
+            ]
+        );
+    }
+}
@@ -1,9 +1,51 @@
+mod actions;
 mod app_impl;
+mod compare;
+mod context;
+mod find;
+mod font_settings;
 mod frame_impl;
+mod history;
+mod keymap;
+mod library;
+mod markdown;
+mod models;
+mod output;
+mod palette;
+mod panes;
+mod preferences;
+mod queue;
+mod recovery;
+mod remote;
+mod settings;
+mod shortcuts;
+mod templates;
+mod theme;
+mod title;
 
-use eframe::egui::{FontId, ImageSource};
+use crate::logics::STATE;
+use eframe::egui::text::{CCursor, CCursorRange};
+use eframe::egui::{text_edit::TextEditState, Context, FontId, Id, ImageSource};
+use egui_commonmark::CommonMarkCache;
 use tokio::runtime::Runtime;
 
+use self::compare::CompareUi;
+use self::find::FindBar;
+use self::font_settings::FontsUi;
+use self::history::History;
+use self::keymap::Keymap;
+use self::library::LibraryUi;
+use self::models::ModelPicker;
+use self::output::OutputLayout;
+use self::palette::Palette;
+use self::panes::Panes;
+use self::preferences::PreferencesUi;
+use self::recovery::Journal;
+use self::settings::Settings;
+use self::shortcuts::ShortcutsUi;
+use self::templates::TemplatesUi;
+use self::theme::Themes;
+
 #[derive(Debug)]
 pub struct LlamaApp {
     logo: ImageSource<'static>,
@@ -14,7 +56,26 @@ pub struct LlamaApp {
     box_layout: BoxLayout,
     setupdone: bool,
     temperature: f32,
-    last_temperature: f32,
+    system_open: bool,
+    context_open: bool,
+    templates: TemplatesUi,
+    keymap: Keymap,
+    palette: Palette,
+    shortcuts: ShortcutsUi,
+    themes: Themes,
+    fonts: FontsUi,
+    panes: Panes,
+    output: OutputLayout,
+    find: FindBar,
+    history: History,
+    journal: Journal,
+    window_title: String,
+    compare: CompareUi,
+    library: LibraryUi,
+    model_picker: ModelPicker,
+    preferences: PreferencesUi,
+    /// Last settings written to storage
+    stored: Settings,
 }
 
 #[derive(Debug, Default, Eq, PartialEq)]
@@ -27,3 +88,70 @@ enum BoxLayout {
 
 #[dynamic]
 static RUNTIME: Runtime = Runtime::new().unwrap();
+
+#[dynamic]
+static mut MD_CACHE: CommonMarkCache = CommonMarkCache::default();
+
+const INPUT_ID: &str = "input";
+
+/// Inserts text into the input replacing the current selection
+fn insert_into_input(ctx: &Context, text: &str) {
+    let id = Id::new(INPUT_ID);
+    let mut edit = TextEditState::load(ctx, id).unwrap_or_default();
+    let mut state = STATE.write();
+    let len = state.input.chars().count();
+    let (start, end) = match edit.cursor.char_range() {
+        Some(range) => {
+            let (a, b) = (range.primary.index.min(len), range.secondary.index.min(len));
+            (a.min(b), a.max(b))
+        }
+        None => (len, len),
+    };
+    let byte = |idx: usize| {
+        state
+            .input
+            .char_indices()
+            .nth(idx)
+            .map(|(i, _)| i)
+            .unwrap_or(state.input.len())
+    };
+    let (start_byte, end_byte) = (byte(start), byte(end));
+    state.input.replace_range(start_byte..end_byte, text);
+    let cursor = CCursor::new(start + text.chars().count());
+    edit.cursor.set_char_range(Some(CCursorRange::one(cursor)));
+    edit.store(ctx, id);
+    ctx.memory_mut(|mem| mem.request_focus(id));
+}
+
+/// Appends text at the end of the input as a follow-up
+fn append_to_input(ctx: &Context, text: &str) {
+    let id = Id::new(INPUT_ID);
+    let mut state = STATE.write();
+    if !state.input.is_empty() && !state.input.ends_with("\n\n") {
+        let separator = if state.input.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        state.input.push_str(separator);
+    }
+    state.input.push_str(text);
+    state.input.push('\n');
+    let mut edit = TextEditState::load(ctx, id).unwrap_or_default();
+    let cursor = CCursor::new(state.input.chars().count());
+    edit.cursor.set_char_range(Some(CCursorRange::one(cursor)));
+    edit.store(ctx, id);
+    ctx.memory_mut(|mem| mem.request_focus(id));
+}
+
+/// Replaces the whole input, leaving the cursor at the end
+fn replace_input(ctx: &Context, text: &str) {
+    let id = Id::new(INPUT_ID);
+    let mut state = STATE.write();
+    state.input = text.to_owned();
+    let mut edit = TextEditState::load(ctx, id).unwrap_or_default();
+    let cursor = CCursor::new(state.input.chars().count());
+    edit.cursor.set_char_range(Some(CCursorRange::one(cursor)));
+    edit.store(ctx, id);
+    ctx.memory_mut(|mem| mem.request_focus(id));
+}
@@ -0,0 +1,418 @@
+use super::{LlamaApp, RUNTIME};
+use crate::logics::models::{self, keep_alive_label, KEEP_ALIVES};
+use crate::logics::STATE;
+use crate::protocol::{AIModel, LoadedModel};
+use chrono::{DateTime, Datelike, Local};
+use eframe::egui::popup::popup_below_widget;
+use eframe::egui::*;
+
+const PICKER_ID: &str = "model-picker";
+
+/// Search and alias being edited in the model picker
+#[derive(Debug, Default)]
+pub(super) struct ModelPicker {
+    query: String,
+    /// Model whose alias and keep-alive are being edited, with the alias typed so far
+    alias: Option<(String, String)>,
+    /// Last model seen selected, to preload the next one
+    selected: String,
+}
+
+/// What was clicked in the picker
+enum Pick {
+    Select(String),
+    Favorite(String),
+    Edit(String),
+    SetAlias(String, String),
+    KeepAlive(String, Option<String>),
+    Unload(String),
+}
+
+/// Rounded size on disk, such as `4.7 GB`
+#[must_use]
+fn human_size(bytes: u64) -> String {
+    let gb = bytes as f64 / 1e9;
+    if gb >= 1.0 {
+        format!("{:.1} GB", gb)
+    } else {
+        format!("{:.0} MB", bytes as f64 / 1e6)
+    }
+}
+
+/// When Ollama frees the model, such as `unloads at 14:05`
+#[must_use]
+fn expiry(loaded: &LoadedModel) -> String {
+    match DateTime::parse_from_rfc3339(&loaded.expires_at) {
+        // Kept forever shows as a date centuries ahead
+        Ok(expires) if expires.year() > 2100 => "kept loaded".to_string(),
+        Ok(expires) => format!(
+            "unloads at {}",
+            expires.with_timezone(&Local).format("%H:%M")
+        ),
+        Err(_) => "loaded".to_string(),
+    }
+}
+
+fn badge(ui: &mut Ui, text: &str) {
+    if !text.is_empty() {
+        ui.label(
+            RichText::new(text)
+                .small()
+                .monospace()
+                .background_color(ui.visuals().faint_bg_color),
+        );
+    }
+}
+
+impl LlamaApp {
+    /// Model chooser in the header, with search, favorites and families
+    pub(super) fn show_model_picker(&mut self, ui: &mut Ui) {
+        let popup = ui.make_persistent_id(PICKER_ID);
+        let (catalog, current, favorites, aliases, keep_alive, loaded) = {
+            let state = STATE.read();
+            (
+                state.catalog.clone(),
+                state
+                    .models
+                    .get(state.selected_model)
+                    .cloned()
+                    .unwrap_or_default(),
+                state.favorites.clone(),
+                state.aliases.clone(),
+                state.keep_alive.clone(),
+                state.loaded.clone(),
+            )
+        };
+        let loaded_as = |name: &str| loaded.iter().find(|model| model.name == name);
+        let alias_of = |name: &str| {
+            aliases
+                .iter()
+                .find(|(_, model)| *model == name)
+                .map(|(alias, _)| alias.to_owned())
+        };
+
+        ui.label(
+            RichText::new("Model:")
+                .font(self.title_font.clone())
+                .color(self.themes.accent())
+                .strong(),
+        );
+        let text = match alias_of(&current) {
+            Some(alias) => format!("{} ({}) ⏷", current, alias),
+            None => format!("{} ⏷", current),
+        };
+        let button = ui.button(text);
+        if button.clicked() {
+            ui.memory_mut(|mem| mem.toggle_popup(popup));
+            self.model_picker.query.clear();
+            self.model_picker.alias = None;
+            RUNTIME.spawn(models::refresh_loaded());
+        }
+        if let Some(model) = loaded_as(&current) {
+            let names = loaded
+                .iter()
+                .map(|model| model.name.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            ui.label(RichText::new("●").color(self.themes.accent()))
+                .on_hover_text(format!("{}\n\nIn memory:\n{}", expiry(model), names));
+        } else if !current.is_empty()
+            && ui
+                .add(Button::new(RichText::new("○").weak()).frame(false))
+                .on_hover_text("Not loaded, click to preload")
+                .clicked()
+        {
+            RUNTIME.spawn(models::preload(current.to_owned()));
+        }
+
+        // Favorites first, then each family in order of its most recent model
+        let query = self.model_picker.query.to_lowercase();
+        let matches = |model: &AIModel| {
+            query.is_empty()
+                || model.name.to_lowercase().contains(&query)
+                || model.details.family.to_lowercase().contains(&query)
+                || alias_of(&model.name).is_some_and(|alias| alias.to_lowercase().contains(&query))
+        };
+        let mut groups: Vec<(String, Vec<&AIModel>)> = Vec::new();
+        let pinned = catalog
+            .iter()
+            .filter(|model| favorites.contains(&model.name) && matches(model))
+            .collect::<Vec<_>>();
+        if !pinned.is_empty() {
+            groups.push(("★ Favorites".to_string(), pinned));
+        }
+        for model in catalog.iter() {
+            if favorites.contains(&model.name) || !matches(model) {
+                continue;
+            }
+            let family = match model.details.family.as_str() {
+                "" => "other".to_string(),
+                family => family.to_owned(),
+            };
+            match groups.iter_mut().find(|(name, _)| *name == family) {
+                Some((_, models)) => models.push(model),
+                None => groups.push((family, vec![model])),
+            }
+        }
+
+        let mut pick: Option<Pick> = None;
+        popup_below_widget(
+            ui,
+            popup,
+            &button,
+            PopupCloseBehavior::CloseOnClickOutside,
+            |ui| {
+                ui.set_min_width(380.0);
+                let search = ui.add(
+                    TextEdit::singleline(&mut self.model_picker.query)
+                        .hint_text("Search by name, alias or family")
+                        .desired_width(f32::INFINITY),
+                );
+                if button.clicked() {
+                    search.request_focus();
+                }
+                if search.lost_focus() && ui.input(|rd| rd.key_pressed(Key::Enter)) {
+                    if let Some(model) = groups.iter().flat_map(|(_, models)| models).next() {
+                        pick = Some(Pick::Select(model.name.to_owned()));
+                    }
+                }
+                ui.separator();
+
+                ScrollArea::vertical().max_height(420.0).show(ui, |ui| {
+                    if groups.is_empty() {
+                        ui.label(RichText::new("No model matches").weak());
+                    }
+                    for (group, models) in groups.iter() {
+                        ui.label(RichText::new(group).small().strong());
+                        for model in models {
+                            let name = &model.name;
+                            ui.horizontal(|ui| {
+                                let star = if favorites.contains(name) {
+                                    "★"
+                                } else {
+                                    "☆"
+                                };
+                                if ui
+                                    .add(Button::new(star).frame(false))
+                                    .on_hover_text("Pin at the top")
+                                    .clicked()
+                                {
+                                    pick = Some(Pick::Favorite(name.to_owned()));
+                                }
+                                if ui.selectable_label(*name == current, name).clicked() {
+                                    pick = Some(Pick::Select(name.to_owned()));
+                                }
+                                if let Some(alias) = alias_of(name) {
+                                    ui.label(RichText::new(alias).italics().weak());
+                                }
+                                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                    if ui
+                                        .add(Button::new("✏").frame(false))
+                                        .on_hover_text("Alias and keep-alive")
+                                        .clicked()
+                                    {
+                                        pick = Some(Pick::Edit(name.to_owned()));
+                                    }
+                                    if let Some(loaded) = loaded_as(name) {
+                                        if ui
+                                            .add(Button::new("⏏").frame(false))
+                                            .on_hover_text("Unload from memory")
+                                            .clicked()
+                                        {
+                                            pick = Some(Pick::Unload(name.to_owned()));
+                                        }
+                                        ui.label(
+                                            RichText::new("● loaded")
+                                                .small()
+                                                .color(self.themes.accent()),
+                                        )
+                                        .on_hover_text(
+                                            format!(
+                                                "{}, {} in GPU memory",
+                                                expiry(loaded),
+                                                human_size(loaded.size_vram)
+                                            ),
+                                        );
+                                    }
+                                    badge(ui, &human_size(model.size));
+                                    badge(ui, &model.details.quantization_level);
+                                    badge(ui, &model.details.parameter_size);
+                                });
+                            });
+                            if let Some((editing, alias)) = self.model_picker.alias.as_mut() {
+                                if editing == name {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Alias:");
+                                        let edit = ui.add(
+                                            TextEdit::singleline(alias)
+                                                .hint_text("coder")
+                                                .desired_width(120.0),
+                                        );
+                                        let enter = edit.lost_focus()
+                                            && ui.input(|rd| rd.key_pressed(Key::Enter));
+                                        if ui.button("Set").clicked() || enter {
+                                            pick = Some(Pick::SetAlias(
+                                                name.to_owned(),
+                                                alias.trim().to_owned(),
+                                            ));
+                                        }
+                                    });
+                                    ui.horizontal(|ui| {
+                                        ui.label("Keep loaded:");
+                                        let current = keep_alive.get(name);
+                                        ComboBox::from_id_salt(("keep-alive", name))
+                                            .selected_text(match current {
+                                                Some(value) => keep_alive_label(value),
+                                                None => "Ollama default".to_string(),
+                                            })
+                                            .show_ui(ui, |ui| {
+                                                if ui
+                                                    .selectable_label(
+                                                        current.is_none(),
+                                                        "Ollama default",
+                                                    )
+                                                    .clicked()
+                                                {
+                                                    pick = Some(Pick::KeepAlive(
+                                                        name.to_owned(),
+                                                        None,
+                                                    ));
+                                                }
+                                                for value in KEEP_ALIVES {
+                                                    if ui
+                                                        .selectable_label(
+                                                            current.is_some_and(|e| e == value),
+                                                            keep_alive_label(value),
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        pick = Some(Pick::KeepAlive(
+                                                            name.to_owned(),
+                                                            Some(value.to_string()),
+                                                        ));
+                                                    }
+                                                }
+                                            });
+                                    });
+                                }
+                            }
+                        }
+                    }
+                });
+            },
+        );
+
+        match pick {
+            Some(Pick::Select(name)) => {
+                let idx = STATE.read().models.iter().position(|e| *e == name);
+                if let Some(idx) = idx {
+                    let mut state = STATE.write();
+                    state.selected_model = idx;
+                    state.missing_model = None;
+                }
+                ui.memory_mut(|mem| mem.close_popup());
+            }
+            Some(Pick::Favorite(name)) => {
+                let favorites = &mut STATE.write().favorites;
+                match favorites.iter().position(|e| *e == name) {
+                    Some(idx) => {
+                        favorites.remove(idx);
+                    }
+                    None => favorites.push(name),
+                }
+            }
+            Some(Pick::Edit(name)) => {
+                let alias = alias_of(&name).unwrap_or_default();
+                self.model_picker.alias = Some((name, alias));
+            }
+            Some(Pick::SetAlias(name, alias)) => {
+                let aliases = &mut STATE.write().aliases;
+                // One alias per model, and each alias names one model
+                aliases.retain(|key, model| *model != name && *key != alias);
+                if !alias.is_empty() {
+                    aliases.insert(alias, name);
+                }
+                self.model_picker.alias = None;
+            }
+            Some(Pick::KeepAlive(name, keep_alive)) => {
+                let state = &mut STATE.write();
+                match keep_alive {
+                    Some(keep_alive) => state.keep_alive.insert(name, keep_alive),
+                    None => state.keep_alive.remove(&name),
+                };
+            }
+            Some(Pick::Unload(name)) => {
+                RUNTIME.spawn(models::unload(name));
+            }
+            None => (),
+        }
+    }
+
+    /// Loads the selected model and keeps it as long as configured
+    pub(super) fn preload_model(&self) {
+        let state = STATE.read();
+        if let Some(model) = state.models.get(state.selected_model) {
+            RUNTIME.spawn(models::preload(model.to_owned()));
+        }
+    }
+
+    /// Loads a newly selected model in the background, when preloading is on
+    pub(super) fn preload_selected(&mut self) {
+        let (current, preload, loaded) = {
+            let state = STATE.read();
+            if state.missing_model.is_some() {
+                return;
+            }
+            let current = state
+                .models
+                .get(state.selected_model)
+                .cloned()
+                .unwrap_or_default();
+            let loaded = state.loaded.iter().any(|model| model.name == current);
+            (current, state.preload, loaded)
+        };
+        if current == self.model_picker.selected {
+            return;
+        }
+        self.model_picker.selected = current.to_owned();
+        if preload && !loaded && !current.is_empty() {
+            RUNTIME.spawn(models::preload(current));
+        }
+    }
+
+    /// Asks for another model when the remembered one was removed
+    pub(super) fn show_missing_model(&mut self, ctx: &Context) {
+        let Some(missing) = STATE.read().missing_model.clone() else {
+            return;
+        };
+        let models = STATE.read().models.clone();
+        let mut open = true;
+        let mut chosen: Option<usize> = None;
+        Window::new("Model not found")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} is no longer installed. Which model should replace it?",
+                    missing
+                ));
+                ui.separator();
+                ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for (idx, model) in models.iter().enumerate() {
+                        if ui.selectable_label(false, model).clicked() {
+                            chosen = Some(idx);
+                        }
+                    }
+                });
+            });
+
+        if let Some(idx) = chosen {
+            STATE.write().selected_model = idx;
+        }
+        if chosen.is_some() || !open {
+            STATE.write().missing_model = None;
+        }
+    }
+}
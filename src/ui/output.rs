@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+
+use super::find::{local_hits, show_highlighted, show_highlighted_markdown};
+use super::markdown::MarkdownLayouts;
+use super::{append_to_input, LlamaApp, MD_CACHE, RUNTIME};
+use crate::helpers::format_input_to_output;
+use crate::logics::conversation::{extension_for, segments, Segment, Turn};
+use crate::logics::{context, storage, STATE};
+use eframe::egui::*;
+use egui_commonmark::CommonMarkViewer;
+
+/// Extra height laid out above and below the visible part, in screens
+const OVERSCAN: f32 = 1.0;
+
+/// Measured height of each turn, so turns out of sight can be skipped,
+/// and where each conversation was scrolled to
+#[derive(Debug, Default)]
+pub(super) struct OutputLayout {
+    /// Copy of the conversation, so the state isn't locked while it is shown
+    turns: Vec<Turn>,
+    memory: String,
+    markdown: MarkdownLayouts,
+    width: f32,
+    heights: Vec<Option<f32>>,
+    conversation: Option<String>,
+    offset: f32,
+    restore: Option<f32>,
+    jump: bool,
+    positions: HashMap<String, f32>,
+}
+
+impl OutputLayout {
+    pub(super) fn load(value: Option<String>) -> Self {
+        Self {
+            positions: value
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Scroll positions of the recent conversations, as JSON
+    pub(super) fn encode(&self, recent: &[String]) -> Option<String> {
+        let positions = self
+            .positions
+            .iter()
+            .filter(|(path, _)| recent.contains(path))
+            .collect::<HashMap<_, _>>();
+        serde_json::to_string(&positions).ok()
+    }
+}
+
+#[derive(Debug)]
+enum OutputAction {
+    Copy(String),
+    SaveCode { code: String, extension: String },
+    Quote(String),
+    Pin(usize),
+    Exclude(usize),
+}
+
+impl LlamaApp {
+    /// Conversation pane, one block per turn with hover actions
+    pub(super) fn show_output(&mut self, ui: &mut Ui) {
+        self.show_conversation_title(ui);
+        let (syntax_dark, syntax_light) = self.themes.syntax();
+        let viewer = || {
+            CommonMarkViewer::default()
+                .syntax_theme_dark(syntax_dark)
+                .syntax_theme_light(syntax_light)
+        };
+        let layout = &mut self.output;
+        let (path, dropped) = {
+            let state = STATE.read();
+            copy_changed(&mut layout.turns, &state.turns);
+            if layout.memory != state.memory {
+                layout.memory = state.memory.to_owned();
+            }
+            let dropped = context::dropped(&state, state.turns.len(), &state.input);
+            (state.path.clone(), dropped)
+        };
+        let turns = std::mem::take(&mut layout.turns);
+        let memory = std::mem::take(&mut layout.memory);
+        let mut action: Option<OutputAction> = None;
+
+        let width = ui.available_width();
+        if (width - layout.width).abs() > 0.5 {
+            layout.width = width;
+            layout.heights.clear();
+        }
+        if path != layout.conversation {
+            if let Some(path) = layout.conversation.take() {
+                layout.positions.insert(path, layout.offset);
+            }
+            layout.restore = path
+                .as_ref()
+                .and_then(|path| layout.positions.get(path).copied());
+            layout.conversation = path;
+            layout.heights.clear();
+        }
+        layout.heights.resize(turns.len(), None);
+        let row_height = ui.text_style_height(&TextStyle::Body);
+        let find = &mut self.find;
+        find.update(&turns);
+        let target = find.target();
+        let mut found: Option<Rect> = None;
+
+        let mut area = ScrollArea::vertical()
+            .id_salt("output")
+            .auto_shrink([false; 2])
+            .stick_to_bottom(true);
+        if layout.jump {
+            layout.jump = false;
+            area = area.vertical_scroll_offset(f32::MAX);
+        } else if let Some(offset) = layout.restore.take() {
+            area = area.vertical_scroll_offset(offset);
+        }
+        let output = area.show_viewport(ui, |ui, viewport| {
+            let visible = viewport.expand2(Vec2::new(0.0, viewport.height() * OVERSCAN));
+            let mut cache = MD_CACHE.write();
+            let mut top = 0.0;
+            let mut skipped = 0.0;
+            let markdown = &mut layout.markdown;
+            if !memory.is_empty() {
+                let start = ui.cursor().top();
+                show_memory(ui, |ui| markdown.show(ui, &memory, false));
+                ui.separator();
+                top += ui.cursor().top() - start;
+            }
+            for (idx, turn) in turns.iter().enumerate() {
+                let height = layout.heights[idx].unwrap_or_else(|| estimate(turn, row_height));
+                let hidden = top + height < visible.min.y || top > visible.max.y;
+                if hidden && target != Some(idx) {
+                    top += height;
+                    skipped += height;
+                    continue;
+                }
+                if skipped > 0.0 {
+                    ui.add_space(skipped);
+                    skipped = 0.0;
+                }
+
+                let start = ui.cursor().top();
+                let prompt_hits = find.hits(idx, true);
+                let answer_hits = find.hits(idx, false);
+                let monospace = TextStyle::Monospace.resolve(ui.style());
+                let response = ui.scope(|ui| {
+                    if turn.excluded {
+                        ui.multiply_opacity(0.5);
+                    }
+                    show_markers(ui, turn, dropped.contains(&idx));
+                    if !prompt_hits.is_empty() {
+                        ui.indent(("prompt", idx), |ui| {
+                            let rect =
+                                show_highlighted_markdown(ui, &turn.prompt, &prompt_hits, true);
+                            found = found.or(rect);
+                        });
+                    } else if !turn.prompt.is_empty() {
+                        ui.indent(("prompt", idx), |ui| markdown.show(ui, &turn.prompt, true));
+                    }
+                    if let Some(elapsed) = turn.thinking_elapsed() {
+                        let title = if turn.thinking_time.is_some() {
+                            format!("Thought for {:.1}s", elapsed.as_secs_f32())
+                        } else {
+                            format!("Thinking… {:.0}s", elapsed.as_secs_f32())
+                        };
+                        show_thinking(ui, idx, title, |ui| {
+                            markdown.show(ui, &turn.thinking, false);
+                        });
+                    } else if !turn.thinking.is_empty() {
+                        show_thinking(ui, idx, "Thoughts".to_string(), |ui| {
+                            markdown.show(ui, &turn.thinking, false);
+                        });
+                    }
+                    for segment in segments(&turn.answer) {
+                        let offset =
+                            |text: &str| text.as_ptr() as usize - turn.answer.as_ptr() as usize;
+                        match segment {
+                            Segment::Text(text) => {
+                                let hits = local_hits(&answer_hits, offset(text), text.len());
+                                if hits.is_empty() {
+                                    markdown.show(ui, text, false);
+                                } else {
+                                    let rect = show_highlighted_markdown(ui, text, &hits, false);
+                                    found = found.or(rect);
+                                }
+                            }
+                            Segment::Code { code, raw, .. }
+                                if !local_hits(&answer_hits, offset(raw), raw.len()).is_empty() =>
+                            {
+                                let hits = local_hits(&answer_hits, offset(code), code.len());
+                                let frame = Frame::canvas(ui.style()).show(ui, |ui| {
+                                    ui.set_width(ui.available_width());
+                                    show_highlighted(ui, code, &hits, monospace.clone())
+                                });
+                                // A match in the fence line shows the whole block
+                                let fence = local_hits(&answer_hits, offset(raw), raw.len())
+                                    .iter()
+                                    .any(|(_, current)| *current)
+                                    .then_some(frame.response.rect);
+                                found = found.or(frame.inner).or(fence);
+                            }
+                            Segment::Code { lang, code, raw } => {
+                                let rect = ui
+                                    .scope(|ui| viewer().show(ui, &mut cache, raw))
+                                    .response
+                                    .rect;
+                                if ui.rect_contains_pointer(rect) {
+                                    overlay(ui, rect, |ui| {
+                                        if ui.small_button("Quote").clicked() {
+                                            action = Some(OutputAction::Quote(raw.to_owned()));
+                                        }
+                                        if ui.small_button("Save…").clicked() {
+                                            action = Some(OutputAction::SaveCode {
+                                                code: code.to_owned(),
+                                                extension: extension_for(lang),
+                                            });
+                                        }
+                                        if ui.small_button("Copy code").clicked() {
+                                            action = Some(OutputAction::Copy(code.to_owned()));
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    if let Some(error) = turn.error.as_deref() {
+                        ui.colored_label(ui.visuals().error_fg_color, format!("Error: {}", error));
+                    }
+                });
+                ui.separator();
+
+                let rect = response.response.rect;
+                if ui.rect_contains_pointer(rect) {
+                    overlay(ui, rect, |ui| {
+                        let pin = if turn.pinned { "Unpin" } else { "Pin" };
+                        if ui
+                            .small_button(pin)
+                            .on_hover_text("Pinned turns are never dropped nor summarized")
+                            .clicked()
+                        {
+                            action = Some(OutputAction::Pin(idx));
+                        }
+                        let exclude = if turn.excluded { "Include" } else { "Exclude" };
+                        if ui
+                            .small_button(exclude)
+                            .on_hover_text("Excluded turns stay here but are not sent to the model")
+                            .clicked()
+                        {
+                            action = Some(OutputAction::Exclude(idx));
+                        }
+                        if ui.small_button("Quote").clicked() {
+                            action = Some(OutputAction::Quote(turn.answer.to_owned()));
+                        }
+                        if ui.small_button("Copy").clicked() {
+                            action = Some(OutputAction::Copy(turn.answer.to_owned()));
+                        }
+                    });
+                }
+
+                let height = ui.cursor().top() - start;
+                layout.heights[idx] = Some(height);
+                top += height;
+            }
+            if skipped > 0.0 {
+                ui.add_space(skipped);
+            }
+            if let Some(rect) = found.filter(|_| find.scroll) {
+                find.scroll = false;
+                ui.scroll_to_rect(rect, Some(Align::Center));
+            }
+        });
+        self.output.markdown.retain_shown();
+        self.output.turns = turns;
+        self.output.memory = memory;
+
+        if self.find.open {
+            self.find.show(ui, output.inner_rect);
+        }
+        self.show_context_meter(ui, output.inner_rect);
+        let layout = &mut self.output;
+
+        layout.offset = output.state.offset.y;
+        if let Some(path) = layout.conversation.as_ref() {
+            layout.positions.insert(path.to_owned(), layout.offset);
+        }
+        let hidden = output.content_size.y - output.inner_rect.height() - layout.offset;
+        if hidden > row_height {
+            let mut child = ui.new_child(
+                UiBuilder::new()
+                    .max_rect(output.inner_rect.shrink(8.0))
+                    .layout(Layout::bottom_up(Align::Center)),
+            );
+            if child
+                .button("⬇ Jump to latest")
+                .on_hover_text("Scroll down and follow new text")
+                .clicked()
+            {
+                layout.jump = true;
+                ui.ctx().request_repaint();
+            }
+        }
+
+        match action {
+            Some(OutputAction::Copy(text)) => ui.ctx().copy_text(text),
+            Some(OutputAction::SaveCode { code, extension }) => {
+                RUNTIME.spawn(storage::save_snippet(code, extension));
+            }
+            Some(OutputAction::Quote(text)) => {
+                append_to_input(ui.ctx(), &format_input_to_output(text.trim_end()));
+            }
+            Some(OutputAction::Pin(idx)) => {
+                if let Some(turn) = STATE.write().turns.get_mut(idx) {
+                    turn.toggle_pinned();
+                }
+            }
+            Some(OutputAction::Exclude(idx)) => {
+                if let Some(turn) = STATE.write().turns.get_mut(idx) {
+                    turn.toggle_excluded();
+                }
+            }
+            None => (),
+        }
+    }
+}
+
+/// Copies the turns that changed since the last frame
+fn copy_changed(copy: &mut Vec<Turn>, turns: &[Turn]) {
+    copy.truncate(turns.len());
+    for (idx, turn) in turns.iter().enumerate() {
+        match copy.get_mut(idx) {
+            Some(copied) if copied == turn => (),
+            Some(copied) => *copied = turn.clone(),
+            None => copy.push(turn.clone()),
+        }
+    }
+}
+
+/// Rough height of a turn that was never laid out
+fn estimate(turn: &Turn, row_height: f32) -> f32 {
+    let lines = turn.prompt.lines().count() + turn.answer.lines().count() + 2;
+    lines as f32 * row_height
+}
+
+/// Collapsed, dimmed reasoning block above an answer
+fn show_thinking(ui: &mut Ui, idx: usize, title: String, add_contents: impl FnOnce(&mut Ui)) {
+    let weak = ui.visuals().weak_text_color();
+    CollapsingHeader::new(RichText::new(title).color(weak).italics())
+        .id_salt(("thinking", idx))
+        .default_open(false)
+        .show(ui, |ui| {
+            ui.visuals_mut().override_text_color = Some(weak);
+            add_contents(ui);
+        });
+}
+
+/// Summary of the earlier turns, sent in their place
+fn show_memory(ui: &mut Ui, add_contents: impl FnOnce(&mut Ui)) {
+    let weak = ui.visuals().weak_text_color();
+    CollapsingHeader::new(
+        RichText::new("Memory of earlier turns")
+            .color(weak)
+            .italics(),
+    )
+    .id_salt("memory")
+    .default_open(false)
+    .show(ui, add_contents);
+}
+
+/// How the turn goes into the context window, when not as usual
+fn show_markers(ui: &mut Ui, turn: &Turn, dropped: bool) {
+    let mut markers = Vec::new();
+    if turn.pinned {
+        markers.push("📌 pinned");
+    }
+    if turn.excluded {
+        markers.push("excluded from context");
+    } else if turn.summarized {
+        markers.push("in memory");
+    } else if dropped {
+        markers.push("left out of context");
+    }
+    if !markers.is_empty() {
+        ui.label(RichText::new(markers.join(" · ")).small().weak());
+    }
+}
+
+/// Lays buttons over the top right corner of a block without moving the cursor
+fn overlay(ui: &mut Ui, rect: Rect, add_contents: impl FnOnce(&mut Ui)) {
+    let mut child = ui.new_child(
+        UiBuilder::new()
+            .max_rect(rect.shrink(4.0))
+            .layout(Layout::right_to_left(Align::Min)),
+    );
+    add_contents(&mut child);
+}
@@ -0,0 +1,185 @@
+use std::{cmp::Reverse, path::PathBuf};
+
+use super::keymap::Action;
+use super::{LlamaApp, RUNTIME};
+use crate::helpers::fuzzy_score;
+use crate::logics::library::{self, LIBRARY};
+use crate::logics::{storage, STATE};
+use eframe::egui::*;
+
+const MAX_ENTRIES: usize = 24;
+
+#[derive(Debug, Default)]
+pub(super) struct Palette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Entry {
+    Action(Action),
+    Model(usize),
+    Conversation(String),
+    Template(usize),
+}
+
+impl LlamaApp {
+    pub(super) fn toggle_palette(&mut self) {
+        self.palette.open = !self.palette.open;
+        self.palette.query.clear();
+        self.palette.selected = 0;
+        // Saved conversations are searched too, so they must be found first
+        let scanned = {
+            let library = LIBRARY.read();
+            library.scanning || !library.entries.is_empty()
+        };
+        if self.palette.open && !scanned {
+            RUNTIME.spawn(library::scan(self.library.roots.clone()));
+        }
+    }
+
+    pub(super) fn show_palette(&mut self, ctx: &Context) {
+        if !self.palette.open {
+            return;
+        }
+        let entries = self.palette_entries(ctx);
+        // Escape is taken, so it doesn't stop a running answer too
+        let (up, down, enter, escape) = ctx.input_mut(|rd| {
+            (
+                rd.key_pressed(Key::ArrowUp),
+                rd.key_pressed(Key::ArrowDown),
+                rd.key_pressed(Key::Enter),
+                rd.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if up {
+            self.palette.selected = self.palette.selected.saturating_sub(1);
+        }
+        if down && self.palette.selected + 1 < entries.len() {
+            self.palette.selected += 1;
+        }
+        self.palette.selected = self.palette.selected.min(entries.len().saturating_sub(1));
+
+        let mut chosen: Option<Entry> = None;
+        Window::new("Command Palette")
+            .title_bar(false)
+            .collapsible(false)
+            .resizable(false)
+            .fixed_size(Vec2::new(480.0, 0.0))
+            .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 64.0))
+            .show(ctx, |ui| {
+                ui.add(
+                    TextEdit::singleline(&mut self.palette.query)
+                        .hint_text("Type a command, model, conversation or template")
+                        .desired_width(f32::INFINITY),
+                )
+                .request_focus();
+                ui.separator();
+                for (idx, (text, hint, entry)) in entries.iter().enumerate() {
+                    let selected = idx == self.palette.selected;
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(selected, text).clicked() {
+                            chosen = Some(entry.clone());
+                        }
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            ui.label(RichText::new(hint).weak());
+                        });
+                    });
+                }
+                if entries.is_empty() {
+                    ui.label(RichText::new("No match").weak());
+                }
+            });
+
+        if enter && chosen.is_none() {
+            chosen = entries.get(self.palette.selected).map(|e| e.2.clone());
+        }
+        if let Some(entry) = chosen {
+            self.palette.open = false;
+            self.run_entry(ctx, entry);
+        } else if escape {
+            self.palette.open = false;
+        }
+    }
+
+    fn palette_entries(&self, ctx: &Context) -> Vec<(String, String, Entry)> {
+        let mut entries = Vec::new();
+        for action in Action::ALL {
+            entries.push((
+                action.label().to_string(),
+                self.keymap.label(ctx, *action),
+                Entry::Action(*action),
+            ));
+        }
+        let state = STATE.read();
+        for (idx, model) in state.models.iter().enumerate() {
+            // Aliases are part of the label so they can be searched
+            let label = match state.aliases.iter().find(|(_, name)| *name == model) {
+                Some((alias, _)) => format!("Model: {} ({})", model, alias),
+                None => format!("Model: {}", model),
+            };
+            entries.push((label, String::new(), Entry::Model(idx)));
+        }
+        for path in state.recent.iter() {
+            let name = PathBuf::from(path)
+                .file_name()
+                .and_then(|e| e.to_str())
+                .unwrap_or(path)
+                .to_string();
+            entries.push((
+                format!("Open: {}", name),
+                path.to_owned(),
+                Entry::Conversation(path.to_owned()),
+            ));
+        }
+        for entry in LIBRARY.read().entries.iter() {
+            if state.recent.contains(&entry.path) {
+                continue;
+            }
+            entries.push((
+                format!("Open: {}", entry.title),
+                entry.path.to_owned(),
+                Entry::Conversation(entry.path.to_owned()),
+            ));
+        }
+        for (idx, template) in state.templates.iter().enumerate() {
+            entries.push((
+                format!("Template: {}", template.name),
+                String::new(),
+                Entry::Template(idx),
+            ));
+        }
+
+        let mut scored = entries
+            .into_iter()
+            .filter_map(|entry| fuzzy_score(&self.palette.query, &entry.0).map(|s| (s, entry)))
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|e| Reverse(e.0));
+        scored
+            .into_iter()
+            .take(MAX_ENTRIES)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    fn run_entry(&mut self, ctx: &Context, entry: Entry) {
+        match entry {
+            Entry::Action(action) => self.run_action(ctx, action),
+            Entry::Model(idx) => STATE.write().selected_model = idx,
+            Entry::Conversation(path) => {
+                if STATE.read().retrieving {
+                    return;
+                }
+                STATE.write().reset();
+                RUNTIME.spawn(storage::load_path(PathBuf::from(path)));
+            }
+            Entry::Template(idx) => {
+                let template = STATE.read().templates.get(idx).cloned();
+                if let Some(template) = template {
+                    self.use_template(ctx, template);
+                }
+            }
+        }
+    }
+}
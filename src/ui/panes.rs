@@ -0,0 +1,156 @@
+use super::{BoxLayout, LlamaApp, INPUT_ID};
+use crate::logics::STATE;
+use eframe::egui::*;
+
+const SPLITTER_WIDTH: f32 = 8.0;
+const MIN_RATIO: f32 = 0.1;
+const MAX_RATIO: f32 = 0.9;
+
+/// How the central panel is divided between input and output
+#[derive(Debug)]
+pub(super) struct Panes {
+    horizontal: f32,
+    vertical: f32,
+    pub(super) compact: bool,
+    pub(super) focus: bool,
+}
+
+impl Default for Panes {
+    fn default() -> Self {
+        Self {
+            horizontal: 3.0 / 7.0,
+            vertical: 1.0 / 3.0,
+            compact: false,
+            focus: false,
+        }
+    }
+}
+
+impl Panes {
+    /// Input share of the central panel in horizontal and vertical layouts
+    #[must_use]
+    pub(super) fn ratios(&self) -> (f32, f32) {
+        (self.horizontal, self.vertical)
+    }
+
+    pub(super) fn set_ratios(&mut self, horizontal: f32, vertical: f32) {
+        self.horizontal = horizontal.clamp(MIN_RATIO, MAX_RATIO);
+        self.vertical = vertical.clamp(MIN_RATIO, MAX_RATIO);
+    }
+}
+
+impl LlamaApp {
+    pub(super) fn toggle_compact_input(&mut self) {
+        self.panes.compact = !self.panes.compact;
+    }
+
+    pub(super) fn toggle_focus_mode(&mut self) {
+        self.panes.focus = !self.panes.focus;
+    }
+
+    /// Single-line chat box growing with its content, docked at the bottom
+    pub(super) fn show_chat_box(&mut self, ctx: &Context) -> Response {
+        let max_height = ctx.screen_rect().height() / 3.0;
+        TopBottomPanel::bottom("chat")
+            .resizable(false)
+            .show(ctx, |ui| {
+                ScrollArea::vertical()
+                    .max_height(max_height)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        ui.add(
+                            TextEdit::multiline(&mut STATE.write().input)
+                                .id(Id::new(INPUT_ID))
+                                .desired_rows(1)
+                                .desired_width(f32::INFINITY)
+                                .hint_text("Message"),
+                        )
+                    })
+                    .inner
+            })
+            .inner
+    }
+
+    /// Input pane, sized by the splitter ratio of the current layout
+    pub(super) fn show_input(&mut self, ui: &mut Ui, size: Vec2) -> Response {
+        let text_size = match self.box_layout {
+            BoxLayout::Horizontally => {
+                Vec2::new((size.x - SPLITTER_WIDTH) * self.panes.horizontal, size.y)
+            }
+            _ => Vec2::new(size.x, (size.y - SPLITTER_WIDTH) * self.panes.vertical),
+        };
+        ScrollArea::vertical()
+            .id_salt("input")
+            .max_width(text_size.x)
+            .max_height(text_size.y)
+            .auto_shrink([false; 2])
+            .show(ui, |ui| {
+                ui.add_sized(
+                    text_size,
+                    TextEdit::multiline(&mut STATE.write().input).id(Id::new(INPUT_ID)),
+                )
+            })
+            .inner
+    }
+
+    /// Draggable handle between input and output
+    pub(super) fn show_splitter(&mut self, ui: &mut Ui, size: Vec2) {
+        let horizontal = self.box_layout == BoxLayout::Horizontally;
+        let (handle, total, cursor) = if horizontal {
+            (
+                Vec2::new(SPLITTER_WIDTH, size.y),
+                size.x - SPLITTER_WIDTH,
+                CursorIcon::ResizeHorizontal,
+            )
+        } else {
+            (
+                Vec2::new(size.x, SPLITTER_WIDTH),
+                size.y - SPLITTER_WIDTH,
+                CursorIcon::ResizeVertical,
+            )
+        };
+        let (rect, response) = ui.allocate_exact_size(handle, Sense::click_and_drag());
+        let response = response.on_hover_cursor(cursor);
+        let stroke = if response.hovered() || response.dragged() {
+            ui.visuals().widgets.hovered.fg_stroke
+        } else {
+            ui.visuals().widgets.noninteractive.bg_stroke
+        };
+        if horizontal {
+            ui.painter().vline(rect.center().x, rect.y_range(), stroke);
+        } else {
+            ui.painter().hline(rect.x_range(), rect.center().y, stroke);
+        }
+
+        if response.dragged() && total > 0.0 {
+            let delta = response.drag_delta();
+            let ratio = if horizontal {
+                &mut self.panes.horizontal
+            } else {
+                &mut self.panes.vertical
+            };
+            let delta = if horizontal { delta.x } else { delta.y };
+            *ratio = (*ratio + delta / total).clamp(MIN_RATIO, MAX_RATIO);
+        }
+        if response.double_clicked() {
+            let default = Panes::default();
+            self.panes.horizontal = default.horizontal;
+            self.panes.vertical = default.vertical;
+        }
+    }
+
+    /// Small floating button to leave focus mode without the keyboard
+    pub(super) fn show_focus_exit(&mut self, ctx: &Context) {
+        Area::new(Id::new("focus-exit"))
+            .anchor(Align2::RIGHT_TOP, Vec2::new(-8.0, 8.0))
+            .show(ctx, |ui| {
+                if ui
+                    .small_button("Exit focus mode")
+                    .on_hover_text("Show the header and footer again")
+                    .clicked()
+                {
+                    self.panes.focus = false;
+                }
+            });
+    }
+}
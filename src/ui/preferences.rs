@@ -0,0 +1,271 @@
+use super::context::size_label;
+use super::panes::Panes;
+use super::settings::{Settings, Think};
+use super::LlamaApp;
+use crate::logics::context::CONTEXT_SIZES;
+use crate::logics::models::Fallback;
+use crate::logics::{STATE, TIMEOUTS};
+use eframe::egui::*;
+use rfd::FileDialog;
+
+#[derive(Debug, Default)]
+pub(super) struct PreferencesUi {
+    open: bool,
+}
+
+impl LlamaApp {
+    pub(super) fn open_preferences(&mut self) {
+        self.preferences.open = true;
+    }
+
+    pub(super) fn show_preferences(&mut self, ctx: &Context) {
+        if !self.preferences.open {
+            return;
+        }
+        let current = self.settings();
+        let mut settings = current.clone();
+        let models = STATE.read().models.clone();
+        let custom_themes = self.themes.custom_names();
+        let mut open = true;
+        let mut windows: Vec<fn(&mut Self)> = Vec::new();
+
+        Window::new("Preferences")
+            .open(&mut open)
+            .default_width(440.0)
+            .show(ctx, |ui| {
+                CollapsingHeader::new(RichText::new("General").strong())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let general = &mut settings.general;
+                        Grid::new("preferences-general")
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                ui.label("Model");
+                                ComboBox::from_id_salt("preferences-model")
+                                    .selected_text(&general.model)
+                                    .show_ui(ui, |ui| {
+                                        for model in models.iter() {
+                                            ui.selectable_value(
+                                                &mut general.model,
+                                                model.to_owned(),
+                                                model,
+                                            );
+                                        }
+                                    });
+                                ui.end_row();
+
+                                ui.label("If it is removed");
+                                ui.horizontal(|ui| {
+                                    for fallback in Fallback::ALL {
+                                        ui.radio_value(
+                                            &mut general.fallback,
+                                            fallback,
+                                            fallback.label(),
+                                        );
+                                    }
+                                });
+                                ui.end_row();
+
+                                ui.label("Loading");
+                                ui.checkbox(&mut settings.models.preload, "Preload when selected")
+                                    .on_hover_text("Load the model in the background as soon as it is picked, so the first answer starts at once");
+                                ui.end_row();
+
+                                ui.label("Timeout");
+                                ComboBox::from_id_salt("preferences-timeout")
+                                    .selected_text(format!("{}s", general.timeout))
+                                    .show_ui(ui, |ui| {
+                                        for timeout in TIMEOUTS {
+                                            ui.selectable_value(
+                                                &mut general.timeout,
+                                                timeout,
+                                                format!("{}s", timeout),
+                                            );
+                                        }
+                                    });
+                                ui.end_row();
+
+                                ui.label("Folder");
+                                ui.horizontal(|ui| {
+                                    if ui.button("Choose…").clicked() {
+                                        if let Some(dir) = FileDialog::new()
+                                            .set_title("Llama Desktop Folder")
+                                            .set_directory(&general.folder)
+                                            .pick_folder()
+                                        {
+                                            general.folder = dir.to_string_lossy().to_string();
+                                        }
+                                    }
+                                    ui.label(RichText::new(&general.folder).small());
+                                });
+                                ui.end_row();
+
+                                ui.label("Notifications");
+                                ui.checkbox(&mut general.notifications, "Notify when done")
+                                    .on_hover_text("Desktop notification when an answer finishes while the window is in the background");
+                                ui.end_row();
+                            });
+                    });
+
+                CollapsingHeader::new(RichText::new("Chat").strong())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let chat = &mut settings.chat;
+                        Grid::new("preferences-chat").num_columns(2).show(ui, |ui| {
+                            ui.label("Temperature");
+                            ui.add(Slider::new(&mut chat.temperature, 0.0..=2.0).step_by(0.125));
+                            ui.end_row();
+
+                            ui.label("Think");
+                            ui.horizontal(|ui| {
+                                ui.radio_value(&mut chat.think, Think::Default, "default");
+                                ui.radio_value(&mut chat.think, Think::On, "on");
+                                ui.radio_value(&mut chat.think, Think::Off, "off");
+                            });
+                            ui.end_row();
+
+                            ui.label("Context window");
+                            ComboBox::from_id_salt("preferences-num-ctx")
+                                .selected_text(size_label(chat.num_ctx))
+                                .show_ui(ui, |ui| {
+                                    for size in CONTEXT_SIZES {
+                                        ui.selectable_value(&mut chat.num_ctx, size, size_label(size));
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("Exports");
+                            ui.checkbox(&mut chat.export_thinking, "Include thinking");
+                            ui.end_row();
+                        });
+                    });
+
+                CollapsingHeader::new(RichText::new("Layout").strong())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let layout = &mut settings.layout;
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut layout.horizontal, false, "Vertical");
+                            ui.radio_value(&mut layout.horizontal, true, "Horizontal");
+                            if ui.button("Reset splitters").clicked() {
+                                let (horizontal, vertical) = Panes::default().ratios();
+                                layout.split_horizontal = horizontal;
+                                layout.split_vertical = vertical;
+                            }
+                        });
+                        ui.checkbox(&mut layout.compact_input, "Compact input");
+                    });
+
+                CollapsingHeader::new(RichText::new("Appearance").strong())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let theme = &mut settings.appearance.theme;
+                        ui.horizontal_wrapped(|ui| {
+                            ui.selectable_value(theme, "dark".to_string(), "Dark");
+                            ui.selectable_value(theme, "light".to_string(), "Light");
+                            ui.selectable_value(theme, "system".to_string(), "Follow system");
+                            for name in custom_themes.iter() {
+                                ui.selectable_value(theme, format!("custom:{}", name), name);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.button("Theme editor…").clicked() {
+                                windows.push(Self::open_theme_editor);
+                            }
+                            if ui.button("Fonts…").clicked() {
+                                windows.push(Self::open_font_settings);
+                            }
+                        });
+                    });
+
+                CollapsingHeader::new(RichText::new("More").strong())
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        ui.horizontal_wrapped(|ui| {
+                            if ui.button("Keyboard shortcuts…").clicked() {
+                                windows.push(Self::open_shortcuts);
+                            }
+                            if ui
+                                .button(format!("Templates ({})…", settings.templates.len()))
+                                .clicked()
+                            {
+                                windows.push(Self::open_template_manager);
+                            }
+                            if ui
+                                .button(format!(
+                                    "Library folders ({})…",
+                                    settings.library.roots.len()
+                                ))
+                                .clicked()
+                            {
+                                windows.push(Self::open_library);
+                            }
+                        });
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Export…").clicked() {
+                        export_settings(&settings);
+                    }
+                    if ui.button("Import…").clicked() {
+                        if let Some(imported) = import_settings(&settings) {
+                            settings = imported;
+                        }
+                    }
+                    if ui
+                        .button("Reset to defaults")
+                        .on_hover_text("Keeps templates and library folders")
+                        .clicked()
+                    {
+                        settings = Settings {
+                            templates: settings.templates.clone(),
+                            library: settings.library.clone(),
+                            ..Default::default()
+                        };
+                    }
+                });
+            });
+        self.preferences.open = open;
+
+        if settings != current {
+            self.apply_settings(ctx, settings);
+        }
+        for window in windows {
+            window(self);
+        }
+    }
+}
+
+fn export_settings(settings: &Settings) {
+    if let Some(path) = FileDialog::new()
+        .set_title("Llama Desktop Export Settings")
+        .set_directory(&settings.general.folder)
+        .set_file_name("llama-desktop.toml")
+        .add_filter("Settings", &["toml"])
+        .save_file()
+    {
+        warn!("exporting settings to {:?}", &path);
+        if let Err(err) = settings.export(&path) {
+            eprintln!("error exporting settings to {:?}", &path);
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+fn import_settings(settings: &Settings) -> Option<Settings> {
+    let path = FileDialog::new()
+        .set_title("Llama Desktop Import Settings")
+        .set_directory(&settings.general.folder)
+        .add_filter("Settings", &["toml"])
+        .pick_file()?;
+    warn!("importing settings from {:?}", &path);
+    match settings.import(&path) {
+        Ok(settings) => Some(settings),
+        Err(err) => {
+            eprintln!("error importing settings from {:?}", &path);
+            eprintln!("{:?}", err);
+            None
+        }
+    }
+}
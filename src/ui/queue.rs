@@ -0,0 +1,117 @@
+use super::{LlamaApp, RUNTIME};
+use crate::logics::compare::COMPARISON;
+use crate::logics::{Sender, STATE};
+use eframe::egui::*;
+
+impl LlamaApp {
+    /// Moves the input to the end of the queue
+    pub(super) fn enqueue_input(&mut self) {
+        let mut state = STATE.write();
+        if state.input.trim().is_empty() {
+            return;
+        }
+        let prompt = std::mem::take(&mut state.input);
+        state.queue.push(prompt);
+    }
+
+    /// Sends the next queued prompt once the current answer, or comparison, is done
+    pub(super) fn run_queue(&mut self) {
+        if COMPARISON.read().running {
+            return;
+        }
+        let prompt = {
+            let mut state = STATE.write();
+            if state.retrieving || state.queue_paused || state.queue.is_empty() {
+                return;
+            }
+            state.queue.remove(0)
+        };
+        RUNTIME.spawn(Sender::queued(self.temperature, prompt).send());
+    }
+
+    /// Edits a copy of the queue, so the answer being streamed can take the
+    /// state while the panel is shown
+    pub(super) fn show_queue(&mut self, ctx: &Context) {
+        let (shown, mut paused) = {
+            let mut state = STATE.write();
+            if state.queue.is_empty() {
+                state.queue_paused = false;
+                return;
+            }
+            (state.queue.clone(), state.queue_paused)
+        };
+        let mut queue = shown.clone();
+        let mut remove: Option<usize> = None;
+        let mut swap: Option<(usize, usize)> = None;
+        TopBottomPanel::bottom("queue")
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(format!("Queued prompts ({})", queue.len())).strong());
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui.small_button("Clear").clicked() {
+                            queue.clear();
+                        }
+                        let text = if paused { "Resume" } else { "Pause" };
+                        if ui.small_button(text).clicked() {
+                            paused = !paused;
+                        }
+                        if paused {
+                            ui.label(RichText::new("paused").color(ui.visuals().warn_fg_color));
+                        }
+                    });
+                });
+                ScrollArea::vertical()
+                    .id_salt("queue")
+                    .max_height(ctx.screen_rect().height() / 4.0)
+                    .show(ui, |ui| {
+                        let count = queue.len();
+                        for (idx, prompt) in queue.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new(format!("{}.", idx + 1)).weak());
+                                ui.with_layout(Layout::right_to_left(Align::Min), |ui| {
+                                    if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                                        remove = Some(idx);
+                                    }
+                                    if ui
+                                        .add_enabled(idx + 1 < count, Button::new("⏷").small())
+                                        .on_hover_text("Move down")
+                                        .clicked()
+                                    {
+                                        swap = Some((idx, idx + 1));
+                                    }
+                                    if ui
+                                        .add_enabled(idx > 0, Button::new("⏶").small())
+                                        .on_hover_text("Move up")
+                                        .clicked()
+                                    {
+                                        swap = Some((idx - 1, idx));
+                                    }
+                                    ui.add(
+                                        TextEdit::multiline(prompt)
+                                            .id_salt(("queued", idx))
+                                            .desired_rows(1)
+                                            .desired_width(f32::INFINITY),
+                                    );
+                                });
+                            });
+                        }
+                    });
+            });
+
+        if let Some(idx) = remove {
+            queue.remove(idx);
+        }
+        if let Some((a, b)) = swap {
+            queue.swap(a, b);
+        }
+
+        let mut state = STATE.write();
+        // Prompts queued meanwhile come after the ones shown
+        if state.queue.starts_with(&shown) {
+            queue.extend(state.queue.split_off(shown.len()));
+            state.queue = queue;
+        }
+        state.queue_paused = paused;
+    }
+}
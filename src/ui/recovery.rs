@@ -0,0 +1,115 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use super::{LlamaApp, RUNTIME};
+use crate::logics::journal::{self, Snapshot};
+use crate::logics::STATE;
+use eframe::egui::*;
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Periodic journaling of the session, and what a crashed one left
+#[derive(Debug)]
+pub(super) struct Journal {
+    recovered: Option<Snapshot>,
+    last_write: Instant,
+    written: u64,
+}
+
+impl Journal {
+    pub(super) fn start() -> Self {
+        Self {
+            recovered: journal::start(),
+            last_write: Instant::now(),
+            written: 0,
+        }
+    }
+}
+
+/// Cheap fingerprint telling whether the session changed since last write
+fn fingerprint() -> u64 {
+    let state = STATE.read();
+    let mut hasher = DefaultHasher::new();
+    state.input.hash(&mut hasher);
+    state.system.hash(&mut hasher);
+    state.path.hash(&mut hasher);
+    state.turns.len().hash(&mut hasher);
+    state.memory.hash(&mut hasher);
+    state.strategy.hash(&mut hasher);
+    for turn in state.turns.iter() {
+        (turn.pinned, turn.excluded).hash(&mut hasher);
+    }
+    if let Some(turn) = state.turns.last() {
+        turn.answer.len().hash(&mut hasher);
+        turn.thinking.len().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+impl LlamaApp {
+    /// Writes the journal when the session changed, at most once per interval
+    pub(super) fn autosave(&mut self, ctx: &Context) {
+        if self.journal.recovered.is_some() {
+            return;
+        }
+        let current = fingerprint();
+        if current == self.journal.written {
+            return;
+        }
+        let elapsed = self.journal.last_write.elapsed();
+        if elapsed < AUTOSAVE_INTERVAL {
+            ctx.request_repaint_after(AUTOSAVE_INTERVAL - elapsed);
+            return;
+        }
+        self.journal.written = current;
+        self.journal.last_write = Instant::now();
+        RUNTIME.spawn(journal::write(Snapshot::take()));
+    }
+
+    pub(super) fn show_recovery(&mut self, ctx: &Context) {
+        let Some(snapshot) = self.journal.recovered.as_ref() else {
+            return;
+        };
+        let mut restore: Option<bool> = None;
+        Window::new("Restore Session")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("Llama Desktop did not exit cleanly last time.");
+                let draft = snapshot.input.chars().count();
+                ui.label(
+                    RichText::new(format!(
+                        "{} turns with {}, and a draft of {} characters.",
+                        snapshot.turns.len(),
+                        if snapshot.model.is_empty() {
+                            "no model"
+                        } else {
+                            &snapshot.model
+                        },
+                        draft,
+                    ))
+                    .weak(),
+                );
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Restore").clicked() {
+                        restore = Some(true);
+                    }
+                    if ui.button("Discard").clicked() {
+                        restore = Some(false);
+                    }
+                });
+            });
+
+        match restore {
+            Some(true) => {
+                if let Some(snapshot) = self.journal.recovered.take() {
+                    snapshot.restore();
+                }
+            }
+            Some(false) => self.journal.recovered = None,
+            None => (),
+        }
+    }
+}
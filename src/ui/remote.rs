@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use super::{replace_input, LlamaApp, RUNTIME};
+use crate::logics::ipc::{Command, INBOX};
+use crate::logics::{set_model, storage, STATE};
+use eframe::egui::{Context, ViewportCommand};
+
+impl LlamaApp {
+    /// Runs what came in through the command socket or the command line
+    pub(super) fn run_remote_commands(&mut self, ctx: &Context) {
+        let mut commands = std::mem::take(&mut *INBOX.write());
+        // The model and prompt given along with files would be lost to the
+        // reset and to the file's header, so they run once the last one is loaded
+        let mut deferred = Vec::new();
+        if commands
+            .iter()
+            .any(|command| matches!(command, Command::Open { .. }))
+        {
+            let (later, now) = commands.into_iter().partition(|command| {
+                matches!(
+                    command,
+                    Command::Model { .. } | Command::Input { .. } | Command::Ask { .. }
+                )
+            });
+            deferred = later;
+            commands = now;
+        }
+        // Conversations replace each other, so only the last file is opened
+        let last_open = commands
+            .iter()
+            .rposition(|command| matches!(command, Command::Open { .. }));
+        for (idx, command) in commands.into_iter().enumerate() {
+            warn!("remote command: {:?}", &command);
+            match command {
+                Command::Focus => {
+                    ctx.send_viewport_cmd(ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(ViewportCommand::Focus);
+                }
+                Command::Open { path } if Some(idx) != last_open => {
+                    warn!("not opening {}, a later file replaces it", path);
+                }
+                Command::Open { path } => {
+                    let then = std::mem::take(&mut deferred);
+                    let mut state = STATE.write();
+                    if state.retrieving {
+                        warn!("busy, not opening {}", path);
+                        drop(state);
+                        INBOX.write().extend(then);
+                        continue;
+                    }
+                    state.reset();
+                    // Busy from now on, not only once the load task starts
+                    state.retrieving = true;
+                    drop(state);
+                    RUNTIME.spawn(storage::load_path_then(PathBuf::from(path), then));
+                    ctx.send_viewport_cmd(ViewportCommand::Focus);
+                }
+                Command::Ask { prompt, model } => {
+                    if let Some(model) = model {
+                        set_model(model);
+                    }
+                    // The queue sends it as soon as nothing else runs
+                    STATE.write().queue.push(prompt);
+                }
+                Command::Model { name } => {
+                    if !set_model(&name) {
+                        eprintln!("model {} not found", name);
+                    }
+                }
+                Command::Input { text } => replace_input(ctx, &text),
+                Command::Status => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use super::keymap::Keymap;
+use super::panes::Panes;
+use super::{BoxLayout, LlamaApp};
+use crate::fonts::FontSettings;
+use crate::logics::models::{self, Fallback};
+use crate::logics::templates::{self, Template};
+use crate::logics::{STATE, TIMEOUTS};
+use eframe::egui::Context;
+use eframe::{Frame, Storage};
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+/// Bumped whenever a field changes meaning, with a step in `migrate`
+const VERSION: i64 = 2;
+const KEY: &str = "settings";
+
+/// Everything the user can configure, stored as TOML under a single key
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Settings {
+    pub version: i64,
+    pub general: General,
+    pub models: Models,
+    pub chat: Chat,
+    pub layout: Layout,
+    pub appearance: Appearance,
+    pub library: Library,
+    pub keymap: Keymap,
+    pub templates: Vec<Template>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct General {
+    /// Name of the selected model
+    pub model: String,
+    /// Its family, to find a replacement when it is removed
+    pub family: String,
+    pub fallback: Fallback,
+    /// Seconds to wait for an answer, one of `TIMEOUTS`
+    pub timeout: usize,
+    /// Where file dialogs start
+    pub folder: String,
+    pub notifications: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Models {
+    pub favorites: Vec<String>,
+    /// Load the model as soon as it is selected
+    pub preload: bool,
+    /// Short name to model name
+    pub aliases: BTreeMap<String, String>,
+    /// Model name to how long it stays loaded, such as `30m`
+    pub keep_alive: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Chat {
+    pub temperature: f32,
+    pub think: Think,
+    pub export_thinking: bool,
+    pub num_ctx: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum Think {
+    /// Leaves it to the model
+    #[default]
+    Default,
+    On,
+    Off,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Layout {
+    /// Input beside the output instead of above it
+    pub horizontal: bool,
+    pub split_horizontal: f32,
+    pub split_vertical: f32,
+    pub compact_input: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Appearance {
+    /// `dark`, `light`, `system` or `custom:NAME`
+    pub theme: String,
+    pub fonts: FontSettings,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(super) struct Library {
+    pub roots: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            general: Default::default(),
+            models: Default::default(),
+            chat: Default::default(),
+            layout: Default::default(),
+            appearance: Default::default(),
+            library: Default::default(),
+            keymap: Default::default(),
+            templates: templates::default_templates(),
+        }
+    }
+}
+
+impl Default for General {
+    fn default() -> Self {
+        Self {
+            model: String::new(),
+            family: String::new(),
+            fallback: Fallback::default(),
+            timeout: TIMEOUTS[1],
+            folder: env!["HOME"].to_string(),
+            notifications: true,
+        }
+    }
+}
+
+impl Default for Models {
+    fn default() -> Self {
+        Self {
+            favorites: Vec::new(),
+            preload: true,
+            aliases: BTreeMap::new(),
+            keep_alive: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for Chat {
+    fn default() -> Self {
+        Self {
+            temperature: 0.75,
+            think: Think::Default,
+            export_thinking: true,
+            num_ctx: 8192,
+        }
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        let (split_horizontal, split_vertical) = Panes::default().ratios();
+        Self {
+            horizontal: false,
+            split_horizontal,
+            split_vertical,
+            compact_input: false,
+        }
+    }
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            theme: "dark".to_string(),
+            fonts: Default::default(),
+        }
+    }
+}
+
+impl From<Think> for Option<bool> {
+    fn from(think: Think) -> Self {
+        match think {
+            Think::Default => None,
+            Think::On => Some(true),
+            Think::Off => Some(false),
+        }
+    }
+}
+
+impl From<Option<bool>> for Think {
+    fn from(think: Option<bool>) -> Self {
+        match think {
+            None => Think::Default,
+            Some(true) => Think::On,
+            Some(false) => Think::Off,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the stored settings, or the separate keys written by older versions
+    #[must_use]
+    pub(super) fn load(storage: &dyn Storage) -> Self {
+        let Some(content) = storage.get_string(KEY) else {
+            return Self::legacy(storage);
+        };
+        match toml::from_str(&content)
+            .map_err(eyre::Report::from)
+            .and_then(Self::from_table)
+        {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("error reading settings: {:?}", err);
+                Self::default()
+            }
+        }
+    }
+
+    fn from_table(mut table: Table) -> Result<Self> {
+        migrate(&mut table);
+        Ok(table.try_into()?)
+    }
+
+    fn legacy(storage: &dyn Storage) -> Self {
+        let get = |key: &str| storage.get_string(key);
+        let default = Self::default();
+        warn!("moving settings from separate keys");
+        Self {
+            version: VERSION,
+            general: General {
+                model: parse(get("selected-model"))
+                    .map(model_at)
+                    .unwrap_or(default.general.model),
+                family: String::new(),
+                fallback: Fallback::default(),
+                timeout: parse(get("timeout")).unwrap_or(default.general.timeout),
+                folder: get("cwd").unwrap_or(default.general.folder),
+                notifications: get("notifications").as_deref() != Some("false"),
+            },
+            models: Default::default(),
+            chat: Chat {
+                temperature: parse(get("temperature")).unwrap_or(default.chat.temperature),
+                think: match get("think").as_deref() {
+                    Some("on") => Think::On,
+                    Some("off") => Think::Off,
+                    _ => Think::Default,
+                },
+                export_thinking: get("export-thinking").as_deref() != Some("false"),
+                num_ctx: parse(get("num-ctx")).unwrap_or(default.chat.num_ctx),
+            },
+            layout: Layout {
+                horizontal: get("layout").as_deref() == Some("H"),
+                split_horizontal: parse(get("split-horizontal"))
+                    .unwrap_or(default.layout.split_horizontal),
+                split_vertical: parse(get("split-vertical"))
+                    .unwrap_or(default.layout.split_vertical),
+                compact_input: get("compact-input").as_deref() == Some("true"),
+            },
+            appearance: Appearance {
+                theme: get("theme").unwrap_or(default.appearance.theme),
+                fonts: get("fonts")
+                    .and_then(|content| serde_json::from_str(&content).ok())
+                    .unwrap_or_default(),
+            },
+            library: Library {
+                roots: get("library-roots")
+                    .and_then(|content| serde_json::from_str(&content).ok())
+                    .unwrap_or_default(),
+            },
+            keymap: get("keymap")
+                .map(|content| Keymap::decode(&content))
+                .unwrap_or_default(),
+            templates: get("templates")
+                .and_then(|content| templates::decode(&content).ok())
+                .unwrap_or(default.templates),
+        }
+    }
+
+    /// Writes the settings to a file someone else can import
+    pub(super) fn export(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Reads a shared file over these settings, so it may hold only some of them
+    pub(super) fn import(&self, path: &Path) -> Result<Self> {
+        let mut table = Table::try_from(self)?;
+        merge(&mut table, toml::from_str(&fs::read_to_string(path)?)?);
+        let mut settings = Self::from_table(table)?;
+        // Folders from another machine may not exist here
+        if !Path::new(&settings.general.folder).is_dir() {
+            settings.general.folder = self.general.folder.to_owned();
+        }
+        settings
+            .library
+            .roots
+            .retain(|root| Path::new(root).is_dir());
+        Ok(settings)
+    }
+}
+
+/// Brings a table written by another version up to date
+fn migrate(table: &mut Table) {
+    let version = table
+        .get("version")
+        .and_then(Value::as_integer)
+        .unwrap_or(VERSION);
+    if version > VERSION {
+        warn!("settings version {} is newer than {}", version, VERSION);
+    }
+    // Up to 1 the model was its position in the list, which changes with every pull
+    if version < 2 {
+        if let Some(Value::Table(general)) = table.get_mut("general") {
+            if let Some(idx) = general.get("model").and_then(Value::as_integer) {
+                let name = model_at(idx as usize);
+                general.insert("model".to_string(), Value::String(name));
+            }
+        }
+    }
+    table.insert("version".to_string(), Value::Integer(VERSION));
+}
+
+fn merge(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(table)), Value::Table(other)) => merge(table, other),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Name of the model at a position of the current list
+fn model_at(idx: usize) -> String {
+    STATE.read().models.get(idx).cloned().unwrap_or_default()
+}
+
+fn parse<T: FromStr>(value: Option<String>) -> Option<T> {
+    value?.parse().ok()
+}
+
+impl LlamaApp {
+    /// Settings currently in effect
+    #[must_use]
+    pub(super) fn settings(&self) -> Settings {
+        let state = STATE.read();
+        let (split_horizontal, split_vertical) = self.panes.ratios();
+        // The model being asked about is still the one to remember
+        let (model, family) = match state.missing_model.as_ref() {
+            Some(missing) => (missing.to_owned(), self.stored.general.family.to_owned()),
+            None => (
+                state
+                    .models
+                    .get(state.selected_model)
+                    .cloned()
+                    .unwrap_or_default(),
+                state
+                    .catalog
+                    .get(state.selected_model)
+                    .map(|model| model.details.family.to_owned())
+                    .unwrap_or_default(),
+            ),
+        };
+        Settings {
+            version: VERSION,
+            general: General {
+                model,
+                family,
+                fallback: state.fallback,
+                timeout: TIMEOUTS[state.timeout_idx],
+                folder: state.cwd.to_owned(),
+                notifications: state.notifications,
+            },
+            models: Models {
+                favorites: state.favorites.clone(),
+                preload: state.preload,
+                aliases: state.aliases.clone(),
+                keep_alive: state.keep_alive.clone(),
+            },
+            chat: Chat {
+                temperature: self.temperature,
+                think: state.think.into(),
+                export_thinking: state.export_thinking,
+                num_ctx: state.num_ctx,
+            },
+            layout: Layout {
+                horizontal: self.box_layout == BoxLayout::Horizontally,
+                split_horizontal,
+                split_vertical,
+                compact_input: self.panes.compact,
+            },
+            appearance: Appearance {
+                theme: self.themes.choice(),
+                fonts: self.fonts.settings.clone(),
+            },
+            library: Library {
+                roots: self.library.roots.clone(),
+            },
+            keymap: self.keymap.clone(),
+            templates: state.templates.clone(),
+        }
+    }
+
+    pub(super) fn apply_settings(&mut self, ctx: &Context, settings: Settings) {
+        let mut state = STATE.write();
+        // Choosing another model answers the question about the missing one
+        if state
+            .missing_model
+            .as_ref()
+            .is_some_and(|missing| *missing != settings.general.model)
+        {
+            state.missing_model = None;
+        }
+        state.fallback = settings.general.fallback;
+        state.favorites = settings.models.favorites;
+        state.aliases = settings.models.aliases;
+        state.keep_alive = settings.models.keep_alive;
+        state.preload = settings.models.preload;
+        state.timeout_idx = TIMEOUTS
+            .iter()
+            .position(|timeout| *timeout == settings.general.timeout)
+            .unwrap_or(1);
+        state.cwd = settings.general.folder;
+        state.notifications = settings.general.notifications;
+        state.think = settings.chat.think.into();
+        state.export_thinking = settings.chat.export_thinking;
+        state.num_ctx = settings.chat.num_ctx;
+        state.templates = settings.templates;
+        drop(state);
+        models::select(&settings.general.model, &settings.general.family);
+
+        self.temperature = settings.chat.temperature;
+        self.box_layout = if settings.layout.horizontal {
+            BoxLayout::Horizontally
+        } else {
+            BoxLayout::Vertically
+        };
+        self.panes.set_ratios(
+            settings.layout.split_horizontal,
+            settings.layout.split_vertical,
+        );
+        self.panes.compact = settings.layout.compact_input;
+        self.themes.set_choice(&settings.appearance.theme);
+        if self.fonts.settings != settings.appearance.fonts {
+            self.fonts.settings = settings.appearance.fonts;
+            self.apply_fonts(ctx);
+        }
+        self.library.roots = settings.library.roots;
+        self.keymap = settings.keymap;
+    }
+
+    /// Writes the settings when they change, waiting for drags to finish
+    pub(super) fn store_settings(&mut self, ctx: &Context, frame: &mut Frame) {
+        if ctx.input(|input| input.pointer.any_down()) {
+            return;
+        }
+        let settings = self.settings();
+        if settings == self.stored {
+            return;
+        }
+        if let Some(storage) = frame.storage_mut() {
+            match toml::to_string(&settings) {
+                Ok(content) => storage.set_string(KEY, content),
+                Err(err) => eprintln!("error encoding settings: {:?}", err),
+            }
+            storage.flush();
+        }
+        self.stored = settings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Key-value storage as eframe keeps it
+    #[derive(Default)]
+    struct Memory(HashMap<String, String>);
+
+    impl Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    /// Models as listed by Ollama when the tests run
+    fn installed() {
+        STATE.write().models = vec!["llama3:8b".to_string(), "mistral:7b".to_string()];
+    }
+
+    #[test]
+    fn v1_model_index_becomes_its_name() {
+        installed();
+        let table: Table = toml::from_str(
+            "version = 1\n[general]\nmodel = 1\ntimeout = 60\n[chat]\ntemperature = 0.5\n",
+        )
+        .unwrap();
+        let settings = Settings::from_table(table).unwrap();
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.general.model, "mistral:7b");
+        assert_eq!(settings.general.timeout, 60);
+        assert_eq!(settings.chat.temperature, 0.5);
+        assert_eq!(settings.chat.num_ctx, Chat::default().num_ctx);
+    }
+
+    #[test]
+    fn v1_index_past_the_list_is_no_model() {
+        installed();
+        let mut table: Table = toml::from_str("version = 1\n[general]\nmodel = 7\n").unwrap();
+        migrate(&mut table);
+        assert_eq!(table["general"]["model"].as_str(), Some(""));
+        assert_eq!(table["version"].as_integer(), Some(VERSION));
+    }
+
+    #[test]
+    fn current_and_newer_versions_keep_their_fields() {
+        let content = "version = 2\n[general]\nmodel = \"phi3\"\n";
+        let mut table: Table = toml::from_str(content).unwrap();
+        migrate(&mut table);
+        assert_eq!(table["general"]["model"].as_str(), Some("phi3"));
+
+        let mut table: Table = toml::from_str("version = 99\nfuture = true\n").unwrap();
+        migrate(&mut table);
+        assert_eq!(table["version"].as_integer(), Some(VERSION));
+        assert_eq!(table["future"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn merge_overrides_only_given_keys() {
+        let mut table: Table = toml::from_str(
+            "[general]\nmodel = \"phi3\"\ntimeout = 60\n[layout]\nhorizontal = true\n",
+        )
+        .unwrap();
+        let other: Table =
+            toml::from_str("[general]\ntimeout = 120\n[library]\nroots = [\"/tmp\"]\n").unwrap();
+        merge(&mut table, other);
+        assert_eq!(table["general"]["model"].as_str(), Some("phi3"));
+        assert_eq!(table["general"]["timeout"].as_integer(), Some(120));
+        assert_eq!(table["layout"]["horizontal"].as_bool(), Some(true));
+        assert_eq!(table["library"]["roots"][0].as_str(), Some("/tmp"));
+    }
+
+    #[test]
+    fn legacy_keys_are_moved() {
+        installed();
+        let mut storage = Memory::default();
+        for (key, value) in [
+            ("selected-model", "1"),
+            ("timeout", "120"),
+            ("temperature", "0.25"),
+            ("think", "off"),
+            ("layout", "H"),
+            ("compact-input", "true"),
+            ("notifications", "false"),
+            ("num-ctx", "not a number"),
+        ] {
+            storage.set_string(key, value.to_string());
+        }
+        let settings = Settings::load(&storage);
+        assert_eq!(settings.version, VERSION);
+        assert_eq!(settings.general.model, "mistral:7b");
+        assert_eq!(settings.general.timeout, 120);
+        assert!(!settings.general.notifications);
+        assert_eq!(settings.chat.temperature, 0.25);
+        assert_eq!(settings.chat.think, Think::Off);
+        assert_eq!(settings.chat.num_ctx, Chat::default().num_ctx);
+        assert!(settings.layout.horizontal && settings.layout.compact_input);
+        assert_eq!(settings.templates, templates::default_templates());
+    }
+
+    #[test]
+    fn stored_settings_round_trip() {
+        let mut settings = Settings::default();
+        settings.general.model = "phi3".to_string();
+        settings.chat.think = Think::On;
+        settings
+            .models
+            .aliases
+            .insert("p".to_string(), "phi3".to_string());
+        let mut storage = Memory::default();
+        storage.set_string(KEY, toml::to_string(&settings).unwrap());
+        assert_eq!(Settings::load(&storage), settings);
+    }
+}
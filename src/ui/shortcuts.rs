@@ -0,0 +1,95 @@
+use super::keymap::{capture, Action, Keymap};
+use super::LlamaApp;
+use eframe::egui::*;
+
+#[derive(Debug, Default)]
+pub(super) struct ShortcutsUi {
+    open: bool,
+    rebinding: Option<Action>,
+}
+
+impl LlamaApp {
+    pub(super) fn open_shortcuts(&mut self) {
+        self.shortcuts.open = true;
+    }
+
+    /// While rebinding, key presses belong to the editor instead of the keymap
+    #[must_use]
+    pub(super) fn capturing_keys(&self) -> bool {
+        self.shortcuts.rebinding.is_some()
+    }
+
+    pub(super) fn show_shortcuts(&mut self, ctx: &Context) {
+        if !self.shortcuts.open {
+            return;
+        }
+
+        if let Some(action) = self.shortcuts.rebinding {
+            if let Some(shortcut) = ctx.input(capture) {
+                if shortcut.modifiers.is_none() && shortcut.logical_key == Key::Escape {
+                    warn!("rebinding {:?} cancelled", action);
+                } else {
+                    self.keymap.set(action, Some(shortcut));
+                }
+                self.shortcuts.rebinding = None;
+            }
+        }
+
+        let mut open = true;
+        Window::new("Keyboard Shortcuts")
+            .open(&mut open)
+            .default_width(520.0)
+            .show(ctx, |ui| {
+                Grid::new("shortcuts")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.label());
+                            if self.shortcuts.rebinding == Some(*action) {
+                                ui.label(RichText::new("Press a key… (Esc cancels)").italics());
+                            } else {
+                                let label = self.keymap.label(ctx, *action);
+                                let conflicts = self.keymap.conflicts(*action);
+                                let text = if label.is_empty() {
+                                    RichText::new("—").weak()
+                                } else if conflicts.is_empty() {
+                                    RichText::new(label).monospace()
+                                } else {
+                                    RichText::new(label).monospace().color(Color32::RED)
+                                };
+                                let response = ui.label(text);
+                                if !conflicts.is_empty() {
+                                    let names = conflicts
+                                        .iter()
+                                        .map(|e| e.label())
+                                        .collect::<Vec<_>>()
+                                        .join(", ");
+                                    response.on_hover_text(format!("Conflicts with: {}", names));
+                                }
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Rebind").clicked() {
+                                    self.shortcuts.rebinding = Some(*action);
+                                }
+                                if ui.small_button("Clear").clicked() {
+                                    self.keymap.set(*action, None);
+                                }
+                                if ui.small_button("Reset").clicked() {
+                                    self.keymap.reset(*action);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                if ui.button("Reset all").clicked() {
+                    self.keymap = Keymap::default();
+                }
+            });
+        if !open {
+            self.shortcuts.open = false;
+            self.shortcuts.rebinding = None;
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use super::{insert_into_input, LlamaApp, RUNTIME};
+use crate::logics::templates::{self, Template};
+use crate::logics::{set_model, STATE};
+use eframe::egui::*;
+
+#[derive(Debug, Default)]
+pub(super) struct TemplatesUi {
+    palette_open: bool,
+    filter: String,
+    form: Option<TemplateForm>,
+    manager_open: bool,
+    editing: Option<usize>,
+}
+
+#[derive(Debug)]
+struct TemplateForm {
+    template: Template,
+    values: Vec<(String, String)>,
+}
+
+impl LlamaApp {
+    pub(super) fn toggle_template_palette(&mut self) {
+        self.templates.palette_open = !self.templates.palette_open;
+        self.templates.filter.clear();
+    }
+
+    pub(super) fn open_template_manager(&mut self) {
+        self.templates.manager_open = true;
+    }
+
+    pub(super) fn show_templates(&mut self, ctx: &Context) {
+        self.show_template_palette(ctx);
+        self.show_template_form(ctx);
+        self.show_template_manager(ctx);
+    }
+
+    /// Starts inserting a template, asking for its variables first if it has any
+    pub(super) fn use_template(&mut self, ctx: &Context, template: Template) {
+        let variables = template.variables();
+        if variables.is_empty() {
+            let text = template.body.to_owned();
+            self.apply_template(ctx, &template, &text);
+        } else {
+            self.templates.form = Some(TemplateForm {
+                template,
+                values: variables.into_iter().map(|e| (e, String::new())).collect(),
+            });
+        }
+    }
+
+    fn apply_template(&mut self, ctx: &Context, template: &Template, text: &str) {
+        if let Some(model) = &template.model {
+            if !set_model(model) {
+                warn!("template model {} not available", model);
+            }
+        }
+        if let Some(temperature) = template.temperature {
+            self.temperature = temperature;
+        }
+        if let Some(system) = &template.system {
+            STATE.write().system = system.to_owned();
+        }
+        insert_into_input(ctx, text);
+    }
+
+    fn show_template_palette(&mut self, ctx: &Context) {
+        if !self.templates.palette_open {
+            return;
+        }
+        let mut open = true;
+        let mut chosen: Option<Template> = None;
+        Window::new("Insert Template")
+            .open(&mut open)
+            .collapsible(false)
+            .anchor(Align2::CENTER_TOP, Vec2::new(0.0, 64.0))
+            .show(ctx, |ui| {
+                let filter = ui.add(
+                    TextEdit::singleline(&mut self.templates.filter).hint_text("Search templates"),
+                );
+                filter.request_focus();
+                let needle = self.templates.filter.to_lowercase();
+                let found = STATE
+                    .read()
+                    .templates
+                    .iter()
+                    .filter(|e| e.name.to_lowercase().contains(&needle))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    for template in found.iter() {
+                        if ui.selectable_label(false, &template.name).clicked() {
+                            chosen = Some(template.clone());
+                        }
+                    }
+                });
+                if filter.lost_focus() && ui.input(|rd| rd.key_pressed(Key::Enter)) {
+                    if let Some(template) = found.first() {
+                        chosen = Some(template.clone());
+                    }
+                }
+            });
+        if let Some(template) = chosen {
+            open = false;
+            self.use_template(ctx, template);
+        }
+        if !open || ctx.input_mut(|rd| rd.consume_key(Modifiers::NONE, Key::Escape)) {
+            self.templates.palette_open = false;
+        }
+    }
+
+    fn show_template_form(&mut self, ctx: &Context) {
+        let Some(form) = self.templates.form.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut insert = false;
+        Window::new(format!("Template: {}", form.template.name))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                Grid::new("template-form").num_columns(2).show(ui, |ui| {
+                    for (name, value) in form.values.iter_mut() {
+                        ui.label(RichText::new(name.as_str()).strong());
+                        ui.add(TextEdit::multiline(value).desired_rows(1));
+                        ui.end_row();
+                    }
+                });
+                ui.separator();
+                insert = ui.button("Insert").clicked();
+            });
+        if insert {
+            let form = self.templates.form.take().unwrap();
+            let values = form.values.into_iter().collect::<HashMap<_, _>>();
+            let text = form.template.render(&values);
+            self.apply_template(ctx, &form.template, &text);
+        } else if !open {
+            self.templates.form = None;
+        }
+    }
+
+    fn show_template_manager(&mut self, ctx: &Context) {
+        if !self.templates.manager_open {
+            return;
+        }
+        let (shown, models) = {
+            let state = STATE.read();
+            (state.templates.clone(), state.models.clone())
+        };
+        let mut edited = shown.clone();
+        let mut open = true;
+        Window::new("Templates")
+            .open(&mut open)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("New").clicked() {
+                        edited.push(Template::new("Untitled", ""));
+                        self.templates.editing = Some(edited.len() - 1);
+                    }
+                    if ui.button("Import pack…").clicked() {
+                        RUNTIME.spawn(templates::import_pack());
+                    }
+                    if ui.button("Export pack…").clicked() {
+                        RUNTIME.spawn(templates::export_pack(edited.clone()));
+                    }
+                });
+                ui.separator();
+
+                ui.horizontal_top(|ui| {
+                    ui.vertical(|ui| {
+                        ui.set_width(180.0);
+                        for (idx, template) in edited.iter().enumerate() {
+                            if ui
+                                .selectable_label(
+                                    self.templates.editing == Some(idx),
+                                    &template.name,
+                                )
+                                .clicked()
+                            {
+                                self.templates.editing = Some(idx);
+                            }
+                        }
+                    });
+                    ui.separator();
+
+                    let Some(idx) = self.templates.editing.filter(|&e| e < edited.len()) else {
+                        ui.label(RichText::new("Select a template").weak());
+                        return;
+                    };
+                    let mut delete = false;
+                    ui.vertical(|ui| {
+                        let template = &mut edited[idx];
+                        ui.label("Name:");
+                        ui.text_edit_singleline(&mut template.name);
+                        ui.label("Prompt (use {{name}} for variables):");
+                        ui.add(TextEdit::multiline(&mut template.body).desired_rows(6));
+
+                        ui.horizontal(|ui| {
+                            ui.label("Model:");
+                            ComboBox::from_id_salt("template-model")
+                                .selected_text(template.model.as_deref().unwrap_or("(current)"))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut template.model, None, "(current)");
+                                    for model in models.iter() {
+                                        ui.selectable_value(
+                                            &mut template.model,
+                                            Some(model.to_owned()),
+                                            model,
+                                        );
+                                    }
+                                });
+                        });
+
+                        ui.horizontal(|ui| {
+                            let mut set = template.temperature.is_some();
+                            if ui.checkbox(&mut set, "Temperature").changed() {
+                                template.temperature = set.then_some(0.75);
+                            }
+                            if let Some(temperature) = template.temperature.as_mut() {
+                                ui.add(Slider::new(temperature, 0.0..=2.0).step_by(0.125));
+                            }
+                        });
+
+                        let mut set = template.system.is_some();
+                        if ui.checkbox(&mut set, "System prompt").changed() {
+                            template.system = set.then(String::new);
+                        }
+                        if let Some(system) = template.system.as_mut() {
+                            ui.add(TextEdit::multiline(system).desired_rows(3));
+                        }
+
+                        ui.separator();
+                        delete = ui.button("Delete").clicked();
+                    });
+                    if delete {
+                        edited.remove(idx);
+                        self.templates.editing = None;
+                    }
+                });
+            });
+
+        if edited != shown {
+            let mut state = STATE.write();
+            // A pack imported meanwhile wins over this frame's edit
+            if state.templates == shown {
+                state.templates = edited;
+            }
+        }
+        self.templates.manager_open = open;
+    }
+}
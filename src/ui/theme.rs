@@ -0,0 +1,502 @@
+use std::{fs, path::PathBuf};
+
+use super::{LlamaApp, MD_CACHE};
+use crate::logics::paths::data_dir;
+use eframe::egui::*;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_ACCENT: Color32 = Color32::from_rgb(0x54, 0x10, 0x21);
+const SYNTAX_DARK: &str = "base16-ocean.dark";
+const SYNTAX_LIGHT: &str = "base16-ocean.light";
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(super) enum ThemeChoice {
+    #[default]
+    Dark,
+    Light,
+    System,
+    Custom(String),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(super) struct CustomTheme {
+    pub name: String,
+    #[serde(default)]
+    pub dark: bool,
+    #[serde(default)]
+    pub panel: PanelColors,
+    #[serde(default)]
+    pub accent: AccentColors,
+    #[serde(default)]
+    pub code: CodeColors,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(super) struct PanelColors {
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub window: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub text: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub faint: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub input: Option<Color32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(super) struct AccentColors {
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub primary: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub secondary: Option<Color32>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub(super) struct CodeColors {
+    /// Name of a built-in highlighting theme, used when no colour is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syntax: Option<String>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub background: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub comment: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub string: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub number: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub function: Option<Color32>,
+    #[serde(default, with = "hex", skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<Color32>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Themes {
+    choice: ThemeChoice,
+    custom: Vec<CustomTheme>,
+    accent: Option<Color32>,
+    syntax: Option<String>,
+    editor_open: bool,
+    dirty: bool,
+}
+
+impl ThemeChoice {
+    fn decode(value: &str) -> Self {
+        match value {
+            "light" => ThemeChoice::Light,
+            "system" => ThemeChoice::System,
+            other => other
+                .strip_prefix("custom:")
+                .map(|name| ThemeChoice::Custom(name.to_owned()))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            ThemeChoice::Dark => "dark".to_string(),
+            ThemeChoice::Light => "light".to_string(),
+            ThemeChoice::System => "system".to_string(),
+            ThemeChoice::Custom(name) => format!("custom:{}", name),
+        }
+    }
+}
+
+impl Themes {
+    pub(super) fn load() -> Self {
+        Self {
+            custom: load_custom_themes(),
+            dirty: true,
+            ..Default::default()
+        }
+    }
+
+    /// Selected theme as stored in the settings: `dark`, `light`, `system` or `custom:NAME`
+    #[must_use]
+    pub(super) fn choice(&self) -> String {
+        self.choice.encode()
+    }
+
+    pub(super) fn set_choice(&mut self, value: &str) {
+        let choice = ThemeChoice::decode(value);
+        if choice != self.choice {
+            self.choice = choice;
+            self.dirty = true;
+        }
+    }
+
+    /// Names of the custom themes found in the data folder
+    #[must_use]
+    pub(super) fn custom_names(&self) -> Vec<String> {
+        self.custom
+            .iter()
+            .map(|theme| theme.name.to_owned())
+            .collect()
+    }
+
+    /// Colour for highlighted labels such as the model picker
+    #[must_use]
+    pub(super) fn accent(&self) -> Color32 {
+        self.accent.unwrap_or(DEFAULT_ACCENT)
+    }
+
+    /// Code block highlighting themes for dark and light modes
+    #[must_use]
+    pub(super) fn syntax(&self) -> (&str, &str) {
+        match &self.syntax {
+            Some(name) => (name, name),
+            None => (SYNTAX_DARK, SYNTAX_LIGHT),
+        }
+    }
+}
+
+impl LlamaApp {
+    pub(super) fn open_theme_editor(&mut self) {
+        self.themes.editor_open = true;
+    }
+
+    /// Applies the selected theme when it has changed
+    pub(super) fn apply_theme(&mut self, ctx: &Context) {
+        if !self.themes.dirty {
+            return;
+        }
+        self.themes.dirty = false;
+        ctx.set_visuals_of(Theme::Dark, Visuals::dark());
+        ctx.set_visuals_of(Theme::Light, Visuals::light());
+        self.themes.accent = None;
+        self.themes.syntax = None;
+
+        let custom = match &self.themes.choice {
+            ThemeChoice::Dark => {
+                ctx.set_theme(ThemePreference::Dark);
+                return;
+            }
+            ThemeChoice::Light => {
+                ctx.set_theme(ThemePreference::Light);
+                return;
+            }
+            ThemeChoice::System => {
+                ctx.set_theme(ThemePreference::System);
+                return;
+            }
+            ThemeChoice::Custom(name) => self.themes.custom.iter().find(|e| &e.name == name),
+        };
+        let Some(custom) = custom.cloned() else {
+            warn!("theme {:?} not found", self.themes.choice);
+            ctx.set_theme(ThemePreference::Dark);
+            return;
+        };
+
+        let theme = if custom.dark {
+            Theme::Dark
+        } else {
+            Theme::Light
+        };
+        ctx.set_visuals_of(theme, custom.visuals());
+        ctx.set_theme(theme);
+        self.themes.accent = custom.accent.primary;
+        self.themes.syntax = custom.code.syntax.clone();
+        if custom.code.has_colors() {
+            let name = format!("llama-{}", custom.name);
+            let tm_theme = custom.code.to_tm_theme(&name, &custom.visuals());
+            match MD_CACHE
+                .write()
+                .add_syntax_theme_from_bytes(&name, tm_theme.as_bytes())
+            {
+                Ok(()) => self.themes.syntax = Some(name),
+                Err(err) => eprintln!("error loading code colours: {:?}", err),
+            }
+        }
+    }
+
+    pub(super) fn show_theme_editor(&mut self, ctx: &Context) {
+        if !self.themes.editor_open {
+            return;
+        }
+        let mut open = true;
+        let previous = self.themes.choice.clone();
+        let mut edited = false;
+        Window::new("Theme")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                let choice = &mut self.themes.choice;
+                ui.horizontal_wrapped(|ui| {
+                    ui.selectable_value(choice, ThemeChoice::Dark, "Dark");
+                    ui.selectable_value(choice, ThemeChoice::Light, "Light");
+                    ui.selectable_value(choice, ThemeChoice::System, "Follow system");
+                    for theme in self.themes.custom.iter() {
+                        ui.selectable_value(
+                            choice,
+                            ThemeChoice::Custom(theme.name.to_owned()),
+                            &theme.name,
+                        );
+                    }
+                });
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.button("New custom theme").clicked() {
+                        let name = format!("Custom {}", self.themes.custom.len() + 1);
+                        self.themes.custom.push(CustomTheme {
+                            name: name.to_owned(),
+                            dark: ctx.style().visuals.dark_mode,
+                            ..Default::default()
+                        });
+                        self.themes.choice = ThemeChoice::Custom(name);
+                    }
+                    if ui.button("Reload themes").clicked() {
+                        self.themes.custom = load_custom_themes();
+                        edited = true;
+                    }
+                });
+
+                let ThemeChoice::Custom(name) = &self.themes.choice else {
+                    return;
+                };
+                let Some(theme) = self.themes.custom.iter_mut().find(|e| &e.name == name) else {
+                    return;
+                };
+                ui.separator();
+                edited |= theme.editor(ui);
+                ui.separator();
+                if ui.button("Save").clicked() {
+                    if let Err(err) = save_custom_theme(theme) {
+                        eprintln!("error saving theme: {:?}", err);
+                    }
+                }
+                if let Some(path) = theme_path(&theme.name) {
+                    ui.label(RichText::new(path.to_string_lossy()).weak().small());
+                }
+            });
+        self.themes.editor_open = open;
+
+        if edited || previous != self.themes.choice {
+            self.themes.dirty = true;
+        }
+    }
+}
+
+impl CustomTheme {
+    #[must_use]
+    fn visuals(&self) -> Visuals {
+        let mut visuals = if self.dark {
+            Visuals::dark()
+        } else {
+            Visuals::light()
+        };
+        if let Some(color) = self.panel.background {
+            visuals.panel_fill = color;
+        }
+        if let Some(color) = self.panel.window {
+            visuals.window_fill = color;
+        }
+        if let Some(color) = self.panel.text {
+            visuals.override_text_color = Some(color);
+        }
+        if let Some(color) = self.panel.faint {
+            visuals.faint_bg_color = color;
+        }
+        if let Some(color) = self.panel.input {
+            visuals.extreme_bg_color = color;
+        }
+        if let Some(color) = self.accent.primary {
+            visuals.selection.bg_fill = color;
+            visuals.hyperlink_color = color;
+        }
+        if let Some(color) = self.accent.secondary {
+            visuals.warn_fg_color = color;
+        }
+        if let Some(color) = self.code.background {
+            visuals.code_bg_color = color;
+        }
+        visuals
+    }
+
+    /// Live editor, returns whether anything changed
+    fn editor(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = false;
+        Grid::new("theme-editor").num_columns(2).show(ui, |ui| {
+            ui.label("Name:");
+            ui.label(&self.name);
+            ui.end_row();
+            ui.label("Base:");
+            ui.horizontal(|ui| {
+                changed |= ui.radio_value(&mut self.dark, true, "Dark").changed();
+                changed |= ui.radio_value(&mut self.dark, false, "Light").changed();
+            });
+            ui.end_row();
+
+            let mut row = |ui: &mut Ui, label: &str, color: &mut Option<Color32>| {
+                ui.label(label);
+                ui.horizontal(|ui| {
+                    let mut set = color.is_some();
+                    if ui.checkbox(&mut set, "").changed() {
+                        *color = set.then_some(Color32::GRAY);
+                        changed = true;
+                    }
+                    if let Some(color) = color.as_mut() {
+                        changed |= ui.color_edit_button_srgba(color).changed();
+                    }
+                });
+                ui.end_row();
+            };
+            row(ui, "Panel background", &mut self.panel.background);
+            row(ui, "Window background", &mut self.panel.window);
+            row(ui, "Text", &mut self.panel.text);
+            row(ui, "Striped rows", &mut self.panel.faint);
+            row(ui, "Input background", &mut self.panel.input);
+            row(ui, "Primary accent", &mut self.accent.primary);
+            row(ui, "Secondary accent", &mut self.accent.secondary);
+            row(ui, "Code background", &mut self.code.background);
+            row(ui, "Code text", &mut self.code.foreground);
+            row(ui, "Code comments", &mut self.code.comment);
+            row(ui, "Code keywords", &mut self.code.keyword);
+            row(ui, "Code strings", &mut self.code.string);
+            row(ui, "Code numbers", &mut self.code.number);
+            row(ui, "Code functions", &mut self.code.function);
+            row(ui, "Code types", &mut self.code.r#type);
+        });
+        changed
+    }
+}
+
+impl CodeColors {
+    #[must_use]
+    fn has_colors(&self) -> bool {
+        [
+            self.background,
+            self.foreground,
+            self.comment,
+            self.keyword,
+            self.string,
+            self.number,
+            self.function,
+            self.r#type,
+        ]
+        .iter()
+        .any(Option::is_some)
+    }
+
+    /// Builds a TextMate theme, the format understood by the code highlighter
+    #[must_use]
+    fn to_tm_theme(&self, name: &str, visuals: &Visuals) -> String {
+        let background = self.background.unwrap_or(visuals.extreme_bg_color);
+        let foreground = self.foreground.unwrap_or(visuals.text_color());
+        let mut res = String::new();
+        res.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        res.push_str("<plist version=\"1.0\"><dict>\n");
+        res.push_str(&format!("<key>name</key><string>{}</string>\n", name));
+        res.push_str("<key>settings</key><array>\n");
+        res.push_str("<dict><key>settings</key><dict>");
+        res.push_str(&format!(
+            "<key>background</key><string>{}</string>",
+            encode_hex(background)
+        ));
+        res.push_str(&format!(
+            "<key>foreground</key><string>{}</string>",
+            encode_hex(foreground)
+        ));
+        res.push_str("</dict></dict>\n");
+        for (scope, color) in [
+            ("comment", self.comment),
+            ("keyword, storage", self.keyword),
+            ("string", self.string),
+            ("constant.numeric, constant.language", self.number),
+            ("entity.name.function, support.function", self.function),
+            ("entity.name.type, support.type, storage.type", self.r#type),
+        ] {
+            if let Some(color) = color {
+                res.push_str(&format!(
+                    "<dict><key>scope</key><string>{}</string><key>settings</key><dict>\
+                     <key>foreground</key><string>{}</string></dict></dict>\n",
+                    scope,
+                    encode_hex(color),
+                ));
+            }
+        }
+        res.push_str("</array>\n</dict></plist>\n");
+        res
+    }
+}
+
+fn load_custom_themes() -> Vec<CustomTheme> {
+    let Some(dir) = data_dir("themes") else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut themes = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("toml"))
+        .filter_map(|path| {
+            let content = fs::read_to_string(&path).ok()?;
+            match toml::from_str::<CustomTheme>(&content) {
+                Ok(theme) => Some(theme),
+                Err(err) => {
+                    eprintln!("error reading theme {:?}: {}", &path, err);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    themes.sort_by(|a, b| a.name.cmp(&b.name));
+    themes
+}
+
+fn theme_path(name: &str) -> Option<PathBuf> {
+    let file = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .to_lowercase();
+    Some(data_dir("themes")?.join(format!("{}.toml", file)))
+}
+
+fn save_custom_theme(theme: &CustomTheme) -> Result<()> {
+    let path = theme_path(&theme.name).ok_or(eyre::eyre!("no data directory"))?;
+    warn!("saving theme to {:?}", &path);
+    fs::write(path, toml::to_string_pretty(theme)?)?;
+    Ok(())
+}
+
+fn encode_hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+mod hex {
+    use eframe::egui::Color32;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Option<Color32>, ser: S) -> Result<S::Ok, S::Error> {
+        match color {
+            Some(color) => ser.serialize_str(&super::encode_hex(*color)),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Color32>, D::Error> {
+        let value = String::deserialize(de)?;
+        let digits = value.trim_start_matches('#');
+        if digits.len() != 6 || !digits.is_ascii() {
+            return Err(D::Error::custom(format!("invalid colour {}", value)));
+        }
+        let channel = |idx: usize| {
+            u8::from_str_radix(&digits[idx..idx + 2], 16)
+                .map_err(|_| D::Error::custom(format!("invalid colour {}", value)))
+        };
+        Ok(Some(Color32::from_rgb(
+            channel(0)?,
+            channel(2)?,
+            channel(4)?,
+        )))
+    }
+}
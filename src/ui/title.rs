@@ -0,0 +1,75 @@
+use super::{LlamaApp, RUNTIME};
+use crate::logics::{titles, STATE};
+use eframe::egui::*;
+
+const APP_TITLE: &str = "Llama Desktop";
+
+impl LlamaApp {
+    /// Tracks window focus and shows the conversation, progress or unread
+    /// answers in the title
+    pub(super) fn update_window_title(&mut self, ctx: &Context) {
+        let focused = ctx.input(|rd| rd.viewport().focused).unwrap_or(true);
+        let title = {
+            let mut state = STATE.write();
+            state.focused = focused;
+            if focused {
+                state.unread = false;
+            }
+            let name = if state.title.is_empty() {
+                APP_TITLE.to_string()
+            } else {
+                format!("{} — {}", state.title, APP_TITLE)
+            };
+            if state.retrieving {
+                let chars = state
+                    .turns
+                    .last()
+                    .map(|turn| turn.answer.chars().count())
+                    .unwrap_or_default();
+                format!("⏳ {} ({} chars)", name, chars)
+            } else if state.unread {
+                format!("● {}", name)
+            } else {
+                name
+            }
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(ViewportCommand::Title(title.to_owned()));
+            self.window_title = title;
+        }
+    }
+
+    /// Editable conversation title above the output
+    pub(super) fn show_conversation_title(&mut self, ui: &mut Ui) {
+        let (empty, retrieving) = {
+            let state = STATE.read();
+            (state.turns.is_empty(), state.retrieving)
+        };
+        if empty {
+            return;
+        }
+        ui.horizontal(|ui| {
+            let mut state = STATE.write();
+            let response = ui.add(
+                TextEdit::singleline(&mut state.title)
+                    .font(TextStyle::Heading)
+                    .frame(false)
+                    .hint_text("Untitled conversation")
+                    .desired_width(ui.available_width() - 96.0),
+            );
+            if response.changed() {
+                state.title_auto = false;
+            }
+            drop(state);
+            self.show_filing(ui);
+            if ui
+                .add_enabled(!retrieving, Button::new("⟳").small())
+                .on_hover_text("Let the model suggest a title")
+                .clicked()
+            {
+                STATE.write().title_auto = true;
+                RUNTIME.spawn(titles::generate());
+            }
+        });
+    }
+}